@@ -36,6 +36,44 @@ fn try_vcpkg() -> LibraryResult<vcpkg::Error, vcpkg::Library> {
     }
 }
 
+#[cfg(feature = "vendored")]
+const VENDORED_SOURCES: &[&str] = &[
+    "file/src/buffer.c",
+    "file/src/magic.c",
+    "file/src/apprentice.c",
+    "file/src/softmagic.c",
+    "file/src/ascmagic.c",
+    "file/src/encoding.c",
+    "file/src/compress.c",
+    "file/src/is_csv.c",
+    "file/src/is_json.c",
+    "file/src/is_simh.c",
+    "file/src/is_tar.c",
+    "file/src/readelf.c",
+    "file/src/print.c",
+    "file/src/fsmagic.c",
+    "file/src/funcs.c",
+    "file/src/apptype.c",
+    "file/src/der.c",
+    "file/src/cdf.c",
+    "file/src/cdf_time.c",
+    "file/src/readcdf.c",
+    "file/src/fmtcheck.c",
+];
+
+#[cfg(feature = "vendored")]
+fn vendored_build(include_dir: &std::path::Path) -> cc::Build {
+    let mut build = cc::Build::new();
+    build
+        .include("file/src")
+        .include(include_dir)
+        .define("HAVE_UNISTD_H", "1")
+        .define("HAVE_INTTYPES_H", "1")
+        .define("VERSION", "5.45")
+        .files(VENDORED_SOURCES);
+    build
+}
+
 #[cfg(feature = "vendored")]
 fn try_vendored() {
     let out_dir = std::env::var("OUT_DIR").unwrap();
@@ -49,34 +87,99 @@ fn try_vendored() {
     data = data.replace("X.YY", "5.45");
     std::fs::write(include_dir.join("magic.h"), &data).unwrap();
 
-    cc::Build::new()
-        .include("file/src")
-        .include(include_dir)
-        .define("HAVE_UNISTD_H", "1")
-        .define("HAVE_INTTYPES_H", "1")
-        .define("VERSION", "5.45")
-        .file("file/src/buffer.c")
-        .file("file/src/magic.c")
-        .file("file/src/apprentice.c")
-        .file("file/src/softmagic.c")
-        .file("file/src/ascmagic.c")
-        .file("file/src/encoding.c")
-        .file("file/src/compress.c")
-        .file("file/src/is_csv.c")
-        .file("file/src/is_json.c")
-        .file("file/src/is_simh.c")
-        .file("file/src/is_tar.c")
-        .file("file/src/readelf.c")
-        .file("file/src/print.c")
-        .file("file/src/fsmagic.c")
-        .file("file/src/funcs.c")
-        .file("file/src/apptype.c")
-        .file("file/src/der.c")
-        .file("file/src/cdf.c")
-        .file("file/src/cdf_time.c")
-        .file("file/src/readcdf.c")
-        .file("file/src/fmtcheck.c")
-        .compile("magic");
+    vendored_build(&include_dir).compile("magic");
+
+    #[cfg(feature = "embedded-db")]
+    compile_embedded_db(out_dir, &include_dir);
+}
+
+/// Compiles the vendored `file/magic/Magdir` sources into `OUT_DIR/magic.mgc`
+///
+/// libmagic can only compile databases through `magic_compile()`, so this builds a
+/// small helper program for the host out of the vendored sources and runs it.
+#[cfg(feature = "embedded-db")]
+fn compile_embedded_db(out_dir: &std::path::Path, include_dir: &std::path::Path) {
+    // compiled databases are stored in the byte order of the machine that compiled
+    // them, and libmagic would have to swap an embedded one in place
+    let target_endian = std::env::var("CARGO_CFG_TARGET_ENDIAN").unwrap();
+    let host_endian = if cfg!(target_endian = "big") {
+        "big"
+    } else {
+        "little"
+    };
+    if target_endian != host_endian {
+        panic!(
+            "the embedded-db feature can't cross-compile from a {}-endian host to a {}-endian target",
+            host_endian, target_endian
+        );
+    }
+
+    let driver = out_dir.join("mkmagic.c");
+    std::fs::write(
+        &driver,
+        r#"#include <stdio.h>
+#include "magic.h"
+
+int main(int argc, char **argv) {
+    magic_t cookie;
+
+    if (argc != 2)
+        return 2;
+    cookie = magic_open(MAGIC_NONE);
+    if (cookie == NULL)
+        return 1;
+    if (magic_compile(cookie, argv[1]) == -1) {
+        fprintf(stderr, "%s\n", magic_error(cookie));
+        return 1;
+    }
+    magic_close(cookie);
+    return 0;
+}
+"#,
+    )
+    .unwrap();
+
+    let host = std::env::var("HOST").unwrap();
+    let compiler = cc::Build::new()
+        .target(&host)
+        .host(&host)
+        .cargo_metadata(false)
+        .get_compiler();
+    let mkmagic = out_dir.join(format!("mkmagic{}", std::env::consts::EXE_SUFFIX));
+    let mut command = compiler.to_command();
+    command
+        .arg("-Ifile/src")
+        .arg(format!("-I{}", include_dir.display()))
+        .arg("-DHAVE_UNISTD_H=1")
+        .arg("-DHAVE_INTTYPES_H=1")
+        .arg("-DVERSION=5.45")
+        .args(VENDORED_SOURCES)
+        .arg(&driver);
+    if compiler.is_like_msvc() {
+        command.arg(format!("/Fe{}", mkmagic.display()));
+    } else {
+        command.arg("-o").arg(&mkmagic);
+    }
+    let status = command
+        .status()
+        .expect("failed to build the magic compiler");
+    assert!(status.success(), "failed to build the magic compiler");
+
+    // magic_compile() names its output after the basename of its argument and
+    // writes it into the current directory
+    let magdir = std::fs::canonicalize("file/magic/Magdir").unwrap();
+    let status = std::process::Command::new(&mkmagic)
+        .arg(&magdir)
+        .current_dir(out_dir)
+        .status()
+        .expect("failed to run the magic compiler");
+    assert!(status.success(), "failed to compile file/magic/Magdir");
+    std::fs::rename(out_dir.join("Magdir.mgc"), out_dir.join("magic.mgc")).unwrap();
+
+    // printing any of these stops Cargo from watching the whole package, and the
+    // magic compiler is built from the vendored sources
+    println!("cargo:rerun-if-changed=file/magic/Magdir");
+    println!("cargo:rerun-if-changed=file/src");
 }
 
 fn main() {
@@ -0,0 +1,64 @@
+//! Classic Mac OS creator and type codes as reported with `Flags::APPLE`
+use std::fmt::{self, Display};
+
+/// The four-character creator and type codes of a classic Mac OS file, like `8BIM` and `PNGf`
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Hash)]
+pub struct AppleType {
+    /// The code of the application that created the file, like `8BIM`
+    pub creator: [u8; 4],
+    /// The code of the kind of file, like `PNGf`
+    pub type_code: [u8; 4],
+}
+
+impl AppleType {
+    /// Returns the creator code as text, replacing bytes that are not printable ASCII
+    pub fn creator_str(&self) -> String {
+        code_str(&self.creator)
+    }
+
+    /// Returns the type code as text, replacing bytes that are not printable ASCII
+    pub fn type_str(&self) -> String {
+        code_str(&self.type_code)
+    }
+
+    /// Returns the creator code followed by the type code, as libmagic prints them
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&self.creator);
+        bytes[4..].copy_from_slice(&self.type_code);
+        bytes
+    }
+}
+
+impl Display for AppleType {
+    /// Formats the codes as `creator/type`, like `8BIM/PNGf`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.creator_str(), self.type_str())
+    }
+}
+
+/// Converts a code into text, since codes are MacRoman and not always ASCII
+fn code_str(code: &[u8; 4]) -> String {
+    code.iter()
+        .map(|&b| {
+            if b == b' ' || b.is_ascii_graphic() {
+                b as char
+            } else {
+                char::REPLACEMENT_CHARACTER
+            }
+        })
+        .collect()
+}
+
+/// Splits the output of `Flags::APPLE | Flags::RAW`, which is `UNKNUNKN` when there is none
+///
+/// Returns `None` for anything that is not two four-byte codes.
+pub(crate) fn parse_apple(output: &[u8]) -> Option<AppleType> {
+    if output == b"UNKNUNKN" || output.len() != 8 {
+        return None;
+    }
+    let mut apple = AppleType::default();
+    apple.creator.copy_from_slice(&output[..4]);
+    apple.type_code.copy_from_slice(&output[4..]);
+    Some(apple)
+}
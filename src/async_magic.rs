@@ -0,0 +1,140 @@
+//! Asynchronous detection for the `tokio` runtime
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::Semaphore,
+    task,
+};
+
+use crate::{ErrorKind, FileMagicError, Flags, Magic};
+
+/// A handle to run `Magic` detections from asynchronous code
+///
+/// Each call checks out one of the handle's cookies and runs on `tokio`'s blocking
+/// thread pool, so at most as many detections as there are cookies run at the same
+/// time and no cookie is ever used by two of them at once. Clones share the cookies.
+#[derive(Clone)]
+pub struct AsyncMagic {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    cookies: Mutex<Vec<Magic<'static>>>,
+    permits: Arc<Semaphore>,
+    bytes_max: usize,
+}
+
+/// Puts a checked out cookie back, even if the detection panicked
+struct Checkout {
+    inner: Arc<Inner>,
+    cookie: Option<Magic<'static>>,
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        if let Some(cookie) = self.cookie.take() {
+            self.inner.cookies.lock().unwrap().push(cookie);
+        }
+    }
+}
+
+impl AsyncMagic {
+    /// Creates `workers` cookies with the given `flags` and `magic_databases`
+    ///
+    /// Without databases, the default one from `Magic::load_default()` is used.
+    pub fn new<P: AsRef<Path>>(
+        flags: Flags,
+        magic_databases: &[P],
+        workers: usize,
+    ) -> Result<AsyncMagic, FileMagicError> {
+        let cookies = (0..workers.max(1))
+            .map(|_| {
+                let cookie = Magic::open(flags)?;
+                if magic_databases.is_empty() {
+                    cookie.load_default()
+                } else {
+                    cookie.load(magic_databases)
+                }
+            })
+            .collect::<Result<Vec<_>, FileMagicError>>()?;
+        Ok(AsyncMagic::from_cookies(cookies))
+    }
+
+    /// Creates a handle that runs detections on the given `cookies`
+    ///
+    /// The cookies should all share the same configuration, since any of them may be
+    /// picked for a call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cookies` is empty.
+    pub fn from_cookies(cookies: Vec<Magic<'static>>) -> AsyncMagic {
+        assert!(!cookies.is_empty(), "AsyncMagic needs at least one cookie");
+        let bytes_max = cookies[0].bytes_max();
+        AsyncMagic {
+            inner: Arc::new(Inner {
+                permits: Arc::new(Semaphore::new(cookies.len())),
+                cookies: Mutex::new(cookies),
+                bytes_max,
+            }),
+        }
+    }
+
+    /// Returns a textual description of the contents of the `filename`
+    pub async fn file<P: AsRef<Path>>(&self, filename: P) -> Result<String, FileMagicError> {
+        let filename: PathBuf = filename.as_ref().into();
+        self.run(move |cookie| cookie.file(filename)).await
+    }
+
+    /// Returns a textual description of the contents of the `buffer`
+    pub async fn buffer(&self, buffer: impl Into<Vec<u8>>) -> Result<String, FileMagicError> {
+        let buffer = buffer.into();
+        self.run(move |cookie| cookie.buffer(&buffer)).await
+    }
+
+    /// Returns a textual description of the data read from `reader`
+    ///
+    /// Only as many bytes as libmagic would look at in a file are read.
+    pub async fn reader<R: AsyncRead + Unpin>(&self, reader: R) -> Result<String, FileMagicError> {
+        let mut buffer = Vec::new();
+        reader
+            .take(self.inner.bytes_max as u64)
+            .read_to_end(&mut buffer)
+            .await
+            .map_err(FileMagicError::io)?;
+        self.buffer(buffer).await
+    }
+
+    /// Runs `detect` with a checked out cookie on the blocking thread pool
+    ///
+    /// The cookie and its permit are only given back once `detect` returns, even if
+    /// the calling future is dropped before that.
+    async fn run<F>(&self, detect: F) -> Result<String, FileMagicError>
+    where
+        F: FnOnce(&Magic<'static>) -> Result<String, FileMagicError> + Send + 'static,
+    {
+        let permit = self
+            .inner
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let inner = self.inner.clone();
+
+        task::spawn_blocking(move || {
+            let _permit = permit;
+            let cookie = inner.cookies.lock().unwrap().pop();
+            let checkout = Checkout { inner, cookie };
+            detect(checkout.cookie.as_ref().expect("a cookie for each permit"))
+        })
+        .await
+        .map_err(|err| {
+            FileMagicError::new(ErrorKind::Other, format!("detection task failed ({})", err))
+        })?
+    }
+}
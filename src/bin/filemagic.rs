@@ -0,0 +1,523 @@
+//! A `file(1)`-compatible command-line interface to libmagic
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::{
+    ffi::{OsStr, OsString},
+    io::{self, Read, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+use filemagic::{ErrorKind, FileMagicError, FileType, Flags, Magic};
+
+const USAGE: &str = "\
+Usage: filemagic [-bcCEhikLNsvzZ0] [--extension] [--json] [--mime-encoding]
+                 [--mime-type] [-F separator] [-m magicfiles] file ...
+       filemagic -C [-m magicfiles]
+       filemagic [--help]
+
+  -b, --brief                do not prepend filenames to output lines
+  -c, --checking-printout    print the parsed form of the magic files
+  -C, --compile              compile the magic files given with -m
+  -E                         exit on the first file that can't be looked at
+  -F, --separator STRING     use STRING as the separator instead of `:'
+  -h, --no-dereference       don't follow symlinks (default)
+  -i, --mime                 output MIME type and encoding strings
+      --mime-type            output the MIME type
+      --mime-encoding        output the MIME encoding
+      --extension            output the usual extensions
+      --json                 output one JSON object per file
+  -k, --keep-going           don't stop at the first match
+  -L, --dereference          follow symlinks
+  -m, --magic-file LIST      use LIST as a colon-separated list of magic files
+  -N, --no-pad               do not pad filenames so that they align
+  -s, --special-files        treat special (block/char devices) files as
+                             ordinary ones
+  -v, --version              output version information and exit
+  -z, --uncompress           try to look inside compressed files
+  -Z, --uncompress-noreport  only look inside compressed files
+  -0, --print0               terminate filenames with ASCII NUL
+      --help                 display this help and exit
+
+Use `-' as a file to read from standard input.";
+
+/// What to do with the magic files
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Action {
+    Detect,
+    Compile,
+    Check,
+}
+
+/// The parsed command line
+#[derive(PartialEq, Eq, Debug)]
+struct Options {
+    action: Action,
+    flags: Flags,
+    databases: Vec<String>,
+    brief: bool,
+    json: bool,
+    no_pad: bool,
+    print0: bool,
+    exit_on_error: bool,
+    separator: String,
+    files: Vec<OsString>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            action: Action::Detect,
+            flags: Flags::NONE,
+            databases: Vec::new(),
+            brief: false,
+            json: false,
+            no_pad: false,
+            print0: false,
+            exit_on_error: false,
+            separator: ":".to_string(),
+            files: Vec::new(),
+        }
+    }
+}
+
+/// What the command line asks for besides looking at files
+#[derive(PartialEq, Eq, Debug)]
+enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+/// Parses the arguments after the program name
+fn parse_args<I: IntoIterator<Item = OsString>>(args: I) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let mut only_files = false;
+
+    while let Some(arg) = args.next() {
+        let text = match arg.to_str() {
+            Some(text) if !only_files && text.starts_with('-') && text != "-" => text.to_string(),
+            _ => {
+                options.files.push(arg);
+                continue;
+            }
+        };
+
+        if text == "--" {
+            only_files = true;
+        } else if let Some(long) = text.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next().map(|v| v.to_string_lossy().into_owned()))
+                    .ok_or_else(|| format!("option `--{}' requires an argument", name))
+            };
+            match name {
+                "help" => return Ok(Command::Help),
+                "version" => return Ok(Command::Version),
+                "magic-file" => options.databases.extend(split_databases(&value()?)),
+                "separator" => options.separator = value()?,
+                "json" => options.json = true,
+                "extension" => options.flags |= Flags::EXTENSION,
+                "mime-type" => options.flags |= Flags::MIME_TYPE,
+                "mime-encoding" => options.flags |= Flags::MIME_ENCODING,
+                _ => match long_to_short(name) {
+                    Some(short) => {
+                        if let Some(command) = apply_short(&mut options, short)? {
+                            return Ok(command);
+                        }
+                    }
+                    None => return Err(format!("unrecognized option `--{}'", name)),
+                },
+            }
+        } else {
+            let mut shorts = text[1..].chars();
+            while let Some(short) = shorts.next() {
+                match short {
+                    'm' | 'F' => {
+                        let rest: String = shorts.by_ref().collect();
+                        let value = if rest.is_empty() {
+                            args.next()
+                                .map(|v| v.to_string_lossy().into_owned())
+                                .ok_or_else(|| {
+                                    format!("option `-{}' requires an argument", short)
+                                })?
+                        } else {
+                            rest
+                        };
+                        if short == 'm' {
+                            options.databases.extend(split_databases(&value));
+                        } else {
+                            options.separator = value;
+                        }
+                    }
+                    _ => {
+                        if let Some(command) = apply_short(&mut options, short)? {
+                            return Ok(command);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if options.action == Action::Detect && options.files.is_empty() {
+        return Err("no files given".to_string());
+    }
+    Ok(Command::Run(options))
+}
+
+/// Returns the short option a long option without an argument stands for
+fn long_to_short(name: &str) -> Option<char> {
+    Some(match name {
+        "brief" => 'b',
+        "checking-printout" => 'c',
+        "compile" => 'C',
+        "no-dereference" => 'h',
+        "mime" => 'i',
+        "keep-going" => 'k',
+        "dereference" => 'L',
+        "no-pad" => 'N',
+        "special-files" => 's',
+        "uncompress" => 'z',
+        "uncompress-noreport" => 'Z',
+        "print0" => '0',
+        _ => return None,
+    })
+}
+
+/// Applies the short option `short` that takes no argument
+fn apply_short(options: &mut Options, short: char) -> Result<Option<Command>, String> {
+    match short {
+        'b' => options.brief = true,
+        'c' => options.action = Action::Check,
+        'C' => options.action = Action::Compile,
+        'E' => options.exit_on_error = true,
+        'h' => options.flags -= Flags::SYMLINK,
+        'i' => options.flags |= Flags::MIME,
+        'k' => options.flags |= Flags::CONTINUE,
+        'L' => options.flags |= Flags::SYMLINK,
+        'N' => options.no_pad = true,
+        's' => options.flags |= Flags::DEVICES,
+        'z' => options.flags |= Flags::COMPRESS,
+        'Z' => options.flags |= Flags::COMPRESS | Flags::COMPRESS_TRANSP,
+        '0' => options.print0 = true,
+        'v' => return Ok(Some(Command::Version)),
+        _ => return Err(format!("invalid option -- '{}'", short)),
+    }
+    Ok(None)
+}
+
+/// Splits a colon-separated list of magic files, like `file -m` takes it
+fn split_databases(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.split(':')
+        .filter(|db| !db.is_empty())
+        .map(str::to_string)
+}
+
+/// Appends `value` to `out` as a JSON string
+fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Formats what was found about the file `name` as a JSON object
+fn json_line(name: &str, result: &Result<FileType, FileMagicError>) -> String {
+    let mut out = String::from("{\"file\":");
+    push_json_string(&mut out, name);
+    match result {
+        Ok(file_type) => {
+            for (key, value) in [
+                ("description", &file_type.description),
+                ("mime_type", &file_type.mime_type),
+                ("mime_encoding", &file_type.mime_encoding),
+            ] {
+                out.push_str(&format!(",\"{}\":", key));
+                push_json_string(&mut out, value);
+            }
+            out.push_str(",\"extensions\":[");
+            for (i, extension) in file_type.extensions.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                push_json_string(&mut out, extension);
+            }
+            out.push(']');
+        }
+        Err(err) => {
+            out.push_str(",\"error\":");
+            push_json_string(&mut out, &err.desc);
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// Returns the name `file` prints for the argument `file`, as the bytes it was given
+fn display_name(file: &OsStr) -> Vec<u8> {
+    if file == "-" {
+        return b"/dev/stdin".to_vec();
+    }
+    #[cfg(unix)]
+    return file.as_bytes().to_vec();
+    #[cfg(not(unix))]
+    return file.to_string_lossy().into_owned().into_bytes();
+}
+
+/// Returns how many columns `name` takes, to pad the names to the same width
+fn name_width(name: &[u8]) -> usize {
+    String::from_utf8_lossy(name).chars().count()
+}
+
+/// Formats the line `file` prints for the file `name`, padding it to `width` columns
+fn format_line(options: &Options, name: &[u8], width: usize, description: &str) -> Vec<u8> {
+    let mut line = Vec::new();
+    if !options.brief {
+        line.extend_from_slice(name);
+        if options.print0 {
+            line.push(b'\0');
+        }
+        line.extend_from_slice(options.separator.as_bytes());
+        if !options.no_pad {
+            line.resize(line.len() + width - name_width(name), b' ');
+        }
+        line.push(b' ');
+    }
+    line.extend_from_slice(description.as_bytes());
+    line.push(b'\n');
+    line
+}
+
+/// Reads all of standard input
+fn read_stdin() -> Result<Vec<u8>, FileMagicError> {
+    let mut buffer = Vec::new();
+    io::stdin()
+        .lock()
+        .read_to_end(&mut buffer)
+        .map(|_| buffer)
+        .map_err(|err| FileMagicError {
+            kind: ErrorKind::Io(err.kind()),
+            desc: format!("cannot read `/dev/stdin' ({})", err),
+            errno: err.raw_os_error(),
+            path: None,
+        })
+}
+
+fn describe(cookie: &Magic, file: &OsStr) -> Result<String, FileMagicError> {
+    if file != "-" {
+        return cookie.file(Path::new(file));
+    }
+    #[cfg(unix)]
+    return cookie.descriptor(&io::stdin());
+    #[cfg(not(unix))]
+    return cookie.reader(io::stdin().lock());
+}
+
+fn identify(cookie: &Magic, file: &OsStr) -> Result<FileType, FileMagicError> {
+    if file == "-" {
+        cookie.identify_buffer(&read_stdin()?)
+    } else {
+        cookie.identify(Path::new(file))
+    }
+}
+
+fn run(options: Options) -> Result<bool, FileMagicError> {
+    let cookie = Magic::open(options.flags)?;
+    match options.action {
+        Action::Compile => {
+            cookie.compile(&options.databases)?;
+            return Ok(true);
+        }
+        Action::Check => {
+            cookie.set_flags(options.flags | Flags::CHECK)?;
+            cookie.check(&options.databases)?;
+            return Ok(true);
+        }
+        Action::Detect => {}
+    }
+    let cookie = if options.databases.is_empty() {
+        cookie.load_default()?
+    } else {
+        cookie.load(&options.databases)?
+    };
+    if !options.exit_on_error && !options.json {
+        // like `file`, describe the errors instead, e.g. `cannot open ...` for missing files
+        cookie.set_flags(options.flags)?;
+    }
+
+    let names: Vec<Vec<u8>> = options.files.iter().map(|f| display_name(f)).collect();
+    let width = names.iter().map(|n| name_width(n)).max().unwrap_or(0);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for (file, name) in options.files.iter().zip(&names) {
+        if options.json {
+            let result = identify(&cookie, file);
+            let failed = result.is_err();
+            let name = String::from_utf8_lossy(name);
+            let _ = writeln!(out, "{}", json_line(&name, &result));
+            if failed && options.exit_on_error {
+                return Ok(false);
+            }
+            continue;
+        }
+
+        let description = match describe(&cookie, file) {
+            Ok(description) => description,
+            Err(err) if options.exit_on_error => {
+                let _ = out.flush();
+                eprintln!("filemagic: {}", err);
+                return Ok(false);
+            }
+            // like `file`, report the error in place of the description
+            Err(err) => err.desc,
+        };
+        let _ = out.write_all(&format_line(&options, name, width, &description));
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args_os().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            let (major, minor) = filemagic::libmagic_version();
+            println!("filemagic-{}", env!("CARGO_PKG_VERSION"));
+            println!("libmagic-{}.{}", major, minor);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("filemagic: {}", message);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("filemagic: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(OsString::from))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn short_options() {
+        let parsed = options(&["-bikLz", "-m", "a:b", "-Fx", "file", "-"]);
+        assert!(parsed.brief);
+        assert_eq!(
+            parsed.flags,
+            Flags::MIME | Flags::CONTINUE | Flags::SYMLINK | Flags::COMPRESS
+        );
+        assert_eq!(parsed.databases, ["a", "b"]);
+        assert_eq!(parsed.separator, "x");
+        assert_eq!(parsed.files, [OsString::from("file"), OsString::from("-")]);
+    }
+
+    #[test]
+    fn long_options() {
+        let parsed = options(&[
+            "--mime-type",
+            "--extension",
+            "--magic-file=db",
+            "--json",
+            "--uncompress-noreport",
+            "--",
+            "-b",
+        ]);
+        assert!(parsed.json);
+        assert!(!parsed.brief);
+        assert_eq!(
+            parsed.flags,
+            Flags::MIME_TYPE | Flags::EXTENSION | Flags::COMPRESS | Flags::COMPRESS_TRANSP
+        );
+        assert_eq!(parsed.databases, ["db"]);
+        assert_eq!(parsed.files, [OsString::from("-b")]);
+    }
+
+    #[test]
+    fn commands_and_errors() {
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-v"]), Ok(Command::Version));
+        assert_eq!(options(&["-C", "-m", "db"]).action, Action::Compile);
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["-q", "file"]).is_err());
+        assert!(parse(&["--bogus", "file"]).is_err());
+        assert!(parse(&["file", "-m"]).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let file_type = FileType {
+            description: "say \"hi\"\n".to_string(),
+            mime_type: "text/plain".to_string(),
+            mime_encoding: "us-ascii".to_string(),
+            extensions: vec!["txt".to_string()],
+            apple: None,
+        };
+        assert_eq!(
+            json_line("a\\b", &Ok(file_type)),
+            "{\"file\":\"a\\\\b\",\"description\":\"say \\\"hi\\\"\\n\",\
+             \"mime_type\":\"text/plain\",\"mime_encoding\":\"us-ascii\",\"extensions\":[\"txt\"]}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn raw_names() {
+        let name = display_name(OsStr::from_bytes(b"caf\xe9"));
+        assert_eq!(name, b"caf\xe9");
+        let parsed = options(&["file"]);
+        assert_eq!(format_line(&parsed, &name, 6, "data"), b"caf\xe9:   data\n");
+    }
+
+    #[test]
+    fn missing_file_like_file() {
+        let cookie = Magic::open(Flags::NONE)
+            .unwrap()
+            .load(&["data/db-images-png"])
+            .unwrap();
+        cookie.set_flags(Flags::NONE).unwrap();
+        assert_eq!(
+            describe(&cookie, OsStr::new("data/missing")).unwrap(),
+            "cannot open `data/missing' (No such file or directory)"
+        );
+    }
+}
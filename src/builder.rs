@@ -0,0 +1,96 @@
+//! Building a ready-to-use `Magic` cookie in one go
+use std::path::{Path, PathBuf};
+
+use crate::{ErrorKind, FileMagicError, Flags, Magic, Param};
+
+/// Collects the `Flags`, magic databases and parameters of a cookie, then creates it
+///
+/// Nothing is checked until `build()`, which fails if the linked libmagic doesn't
+/// support some of the flags or parameters, or if a database doesn't load. Without
+/// databases, the default one from `Magic::load_default()` is used.
+///
+/// ```no_run
+/// use filemagic::{Flags, MagicBuilder, Param};
+///
+/// let cookie = MagicBuilder::new()
+///     .flags(Flags::MIME_TYPE)
+///     .database("data/db-images-png")
+///     .param(Param::BytesMax, 1024 * 1024)
+///     .build()
+///     .expect("error");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MagicBuilder<'db> {
+    flags: Flags,
+    databases: Vec<PathBuf>,
+    buffers: Vec<&'db [u8]>,
+    params: Vec<(Param, usize)>,
+}
+
+impl<'db> MagicBuilder<'db> {
+    /// Creates a builder with the default flags and database
+    pub fn new() -> MagicBuilder<'db> {
+        MagicBuilder::default()
+    }
+
+    /// Sets the flags of the cookie, replacing any set before
+    pub fn flags(mut self, flags: Flags) -> MagicBuilder<'db> {
+        self.flags = flags;
+        self
+    }
+
+    /// Adds the database file `path`, as `Magic::load()` takes it
+    pub fn database<P: AsRef<Path>>(mut self, path: P) -> MagicBuilder<'db> {
+        self.databases.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds a compiled database from memory, as `Magic::load_buffers()` takes it
+    ///
+    /// libmagic can't combine these with database files.
+    pub fn database_bytes(mut self, buffer: &'db [u8]) -> MagicBuilder<'db> {
+        self.buffers.push(buffer);
+        self
+    }
+
+    /// Sets the limit `param` to `value`, replacing any value set before
+    pub fn param(mut self, param: Param, value: usize) -> MagicBuilder<'db> {
+        self.params.retain(|(p, _)| *p != param);
+        self.params.push((param, value));
+        self
+    }
+
+    /// Creates the cookie, sets its parameters and loads its databases
+    ///
+    /// Each database file is loaded on its own first, so that the error names the
+    /// one that doesn't load.
+    pub fn build(&self) -> Result<Magic<'db>, FileMagicError> {
+        if !self.databases.is_empty() && !self.buffers.is_empty() {
+            return Err(FileMagicError::new(
+                ErrorKind::DatabaseLoad,
+                "cannot load database files and in-memory databases together",
+            ));
+        }
+
+        let cookie = Magic::open(self.flags)?;
+        for &(param, value) in &self.params {
+            cookie.set_param(param, value)?;
+        }
+
+        if !self.buffers.is_empty() {
+            cookie.load_buffers(&self.buffers)
+        } else if self.databases.is_empty() {
+            cookie.load_default()
+        } else {
+            let mut cookie = cookie.load(&self.databases[..1])?;
+            // libmagic skips files of a list that don't load as long as one does
+            if self.databases.len() > 1 {
+                for database in &self.databases[1..] {
+                    cookie = cookie.load(&[database])?;
+                }
+                cookie = cookie.load(&self.databases)?;
+            }
+            Ok(cookie)
+        }
+    }
+}
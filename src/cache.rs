@@ -0,0 +1,364 @@
+//! Remembering what libmagic reported about files and buffers that haven't changed
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    fs::{self, Metadata},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+use sha2::{Digest, Sha256};
+
+use crate::{FileMagicError, FileType, Flags, Magic, Param};
+
+/// Which entry a full `CachedMagic` drops to make room for a new one
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Eviction {
+    /// The entry that was looked up the longest time ago
+    #[default]
+    LeastRecentlyUsed,
+    /// The entry that was added the longest time ago, however often it is looked up
+    FirstIn,
+}
+
+/// A cookie that remembers its results for files and buffers it has already seen
+///
+/// Files are recognised by their device, inode, size and modification time, so a file
+/// rewritten within the resolution of its modification time without changing size may
+/// get a stale result. Buffers are recognised by the SHA-256 digest of their contents,
+/// which are not kept. Entries also depend on the flags and parameters of the cookie and
+/// on the databases it loaded, so changing them through `set_flags()` or `set_param()`
+/// doesn't return results for other ones. Errors are not remembered.
+///
+/// Everything else `Magic` does is available through `Deref`, without caching.
+///
+/// ```no_run
+/// use filemagic::{CachedMagic, Eviction, Magic};
+///
+/// let cookie = Magic::open(Default::default())
+///     .and_then(|cookie| cookie.load_default())
+///     .expect("error");
+/// let cookie = CachedMagic::new(cookie, 10_000, Eviction::LeastRecentlyUsed);
+/// let first = cookie.file("/bin/sh").expect("error");
+/// // as long as `/bin/sh` is left alone, libmagic doesn't look at it again
+/// assert_eq!(cookie.file("/bin/sh").expect("error"), first);
+/// ```
+pub struct CachedMagic<'db> {
+    cookie: Magic<'db>,
+    cache: RefCell<Cache>,
+}
+
+impl<'db> Deref for CachedMagic<'db> {
+    type Target = Magic<'db>;
+
+    fn deref(&self) -> &Magic<'db> {
+        &self.cookie
+    }
+}
+
+impl<'db> CachedMagic<'db> {
+    /// Wraps `cookie` with room for `capacity` results, dropping them as `eviction` says
+    ///
+    /// A `capacity` of zero remembers nothing. Each result is kept with a small key,
+    /// however large the file or buffer it is about.
+    pub fn new(cookie: Magic<'db>, capacity: usize, eviction: Eviction) -> CachedMagic<'db> {
+        CachedMagic {
+            cookie,
+            cache: RefCell::new(Cache {
+                capacity,
+                eviction,
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Returns the cookie, forgetting the remembered results
+    pub fn into_inner(self) -> Magic<'db> {
+        self.cookie
+    }
+
+    /// Puts `cookie` in place of the current one, and returns the current one
+    ///
+    /// Results remembered with the databases of `cookie` are kept, the others are only
+    /// dropped as new results need room.
+    pub fn replace(&mut self, cookie: Magic<'db>) -> Magic<'db> {
+        std::mem::replace(&mut self.cookie, cookie)
+    }
+
+    /// Returns how many results are remembered
+    pub fn len(&self) -> usize {
+        self.cache.borrow().entries.len()
+    }
+
+    /// Returns whether no results are remembered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many results can be remembered at most
+    pub fn capacity(&self) -> usize {
+        self.cache.borrow().capacity
+    }
+
+    /// Forgets all remembered results
+    pub fn clear(&self) {
+        let mut cache = self.cache.borrow_mut();
+        cache.entries.clear();
+        cache.order.clear();
+    }
+
+    /// Returns the textual output of `Magic::file()`, remembered if the file hasn't changed
+    pub fn file<P: AsRef<Path>>(&self, filename: P) -> Result<String, FileMagicError> {
+        let filename = filename.as_ref();
+        let key = self.file_key(filename, Query::Text);
+        self.cached(key, || self.cookie.file(filename))
+    }
+
+    /// Returns the textual output of `Magic::buffer()`, remembered by its contents
+    pub fn buffer(&self, buffer: &[u8]) -> Result<String, FileMagicError> {
+        let key = self.buffer_key(buffer, Query::Text);
+        self.cached(key, || self.cookie.buffer(buffer))
+    }
+
+    /// Returns what `Magic::identify()` reports, remembered if the file hasn't changed
+    pub fn identify<P: AsRef<Path>>(&self, filename: P) -> Result<FileType, FileMagicError> {
+        let filename = filename.as_ref();
+        let key = self.file_key(filename, Query::Type);
+        self.cached(key, || self.cookie.identify(filename))
+    }
+
+    /// Returns what `Magic::identify_buffer()` reports, remembered by its contents
+    pub fn identify_buffer(&self, buffer: &[u8]) -> Result<FileType, FileMagicError> {
+        let key = self.buffer_key(buffer, Query::Type);
+        self.cached(key, || self.cookie.identify_buffer(buffer))
+    }
+
+    /// Looks up `key`, or runs `detect` and remembers its result
+    fn cached<T: Cached>(
+        &self,
+        key: Option<Key>,
+        detect: impl FnOnce() -> Result<T, FileMagicError>,
+    ) -> Result<T, FileMagicError> {
+        let key = match key {
+            Some(key) => key,
+            None => return detect(),
+        };
+        if let Some(value) = self.cache.borrow_mut().get(&key).and_then(T::from_value) {
+            return Ok(value);
+        }
+        let result = detect()?;
+        self.cache
+            .borrow_mut()
+            .insert(key, result.clone().into_value());
+        Ok(result)
+    }
+
+    /// Returns the key of the file at `path`, or `None` if it can't be looked at
+    fn file_key(&self, path: &Path, query: Query) -> Option<Key> {
+        let flags = self.cookie.flags();
+        let metadata = if flags.contains(Flags::SYMLINK) {
+            fs::metadata(path)
+        } else {
+            fs::symlink_metadata(path)
+        }
+        .ok()?;
+        let subject = Subject::File {
+            id: file_id(path, &metadata)?,
+            size: metadata.len(),
+            modified: metadata.modified().ok()?,
+        };
+        Some(self.key(subject, query, flags))
+    }
+
+    fn buffer_key(&self, buffer: &[u8], query: Query) -> Option<Key> {
+        let subject = Subject::Buffer {
+            digest: Sha256::digest(buffer).into(),
+            len: buffer.len(),
+        };
+        Some(self.key(subject, query, self.cookie.flags()))
+    }
+
+    fn key(&self, subject: Subject, query: Query, flags: Flags) -> Key {
+        Key {
+            subject,
+            query,
+            flags: flags.bits(),
+            // parameters the linked libmagic doesn't support can't change
+            params: Param::ALL
+                .iter()
+                .map(|&param| self.cookie.get_param(param).ok())
+                .collect(),
+            database: self.cookie.database_id(),
+        }
+    }
+}
+
+/// What identifies a file regardless of its path
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &Metadata) -> Option<FileId> {
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// What identifies a file regardless of its path
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &Metadata) -> Option<FileId> {
+    fs::canonicalize(path).ok()
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Query {
+    Text,
+    Type,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+enum Subject {
+    File {
+        id: FileId,
+        size: u64,
+        modified: SystemTime,
+    },
+    Buffer {
+        digest: [u8; 32],
+        len: usize,
+    },
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+struct Key {
+    subject: Subject,
+    query: Query,
+    flags: i32,
+    params: Vec<Option<usize>>,
+    database: u64,
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Text(String),
+    Type(FileType),
+}
+
+/// The results a `CachedMagic` remembers
+trait Cached: Clone {
+    fn into_value(self) -> Value;
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl Cached for String {
+    fn into_value(self) -> Value {
+        Value::Text(self)
+    }
+
+    fn from_value(value: Value) -> Option<String> {
+        match value {
+            Value::Text(text) => Some(text),
+            Value::Type(_) => None,
+        }
+    }
+}
+
+impl Cached for FileType {
+    fn into_value(self) -> Value {
+        Value::Type(self)
+    }
+
+    fn from_value(value: Value) -> Option<FileType> {
+        match value {
+            Value::Type(file_type) => Some(file_type),
+            Value::Text(_) => None,
+        }
+    }
+}
+
+struct Cache {
+    capacity: usize,
+    eviction: Eviction,
+    // the values, and when they were last used or added
+    entries: HashMap<Key, (Value, u64)>,
+    order: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+impl Cache {
+    fn get(&mut self, key: &Key) -> Option<Value> {
+        self.tick += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        if self.eviction == Eviction::LeastRecentlyUsed {
+            let key = self.order.remove(used).unwrap();
+            *used = self.tick;
+            self.order.insert(self.tick, key);
+        }
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: Key, value: Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Identifies the database files loaded by `Magic::load()`, as they are at the time
+///
+/// libmagic prefers `<name>.mgc` to `<name>`, so both count. Without filenames,
+/// libmagic loads the database named by `$MAGIC`, or its own default one.
+pub(crate) fn files_database_id<P: AsRef<Path>>(filenames: &[P]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if filenames.is_empty() {
+        std::env::var_os("MAGIC").hash(&mut hasher);
+    }
+    for filename in filenames {
+        let path = filename.as_ref();
+        let mut compiled = path.as_os_str().to_os_string();
+        compiled.push(".mgc");
+        for path in [path.to_path_buf(), PathBuf::from(compiled)] {
+            path.hash(&mut hasher);
+            if let Ok(metadata) = fs::metadata(&path) {
+                metadata.len().hash(&mut hasher);
+                metadata.modified().ok().hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+/// Identifies the databases loaded by `Magic::load_buffers()` by their contents
+pub(crate) fn buffers_database_id(buffers: &[&[u8]]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for buffer in buffers {
+        hash_bytes(buffer).hash(&mut hasher);
+    }
+    hasher.finish()
+}
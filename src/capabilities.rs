@@ -0,0 +1,111 @@
+//! What the linked `libmagic` supports
+use crate::{version, FileMagicError, Flags, Magic, Param};
+
+/// The features of the linked `libmagic`
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Capabilities {
+    /// The version of `libmagic`, as `(MAJOR, MINOR)`
+    pub version: (u32, u32),
+    /// The flags `libmagic` knows, others are refused by `Magic::set_flags`
+    pub flags: Flags,
+    /// The parameters `libmagic` knows, others are refused by `Magic::set_param`
+    pub params: Vec<Param>,
+    /// The compression formats `libmagic` can look into by itself
+    pub decompressors: Decompressors,
+}
+
+/// The compression formats `libmagic` can look into without running external programs
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Decompressors {
+    /// gzip and zlib streams
+    pub zlib: bool,
+    /// bzip2
+    pub bzip2: bool,
+    /// xz and lzma
+    pub xz: bool,
+    /// Zstandard
+    pub zstd: bool,
+    /// lzip
+    pub lzip: bool,
+}
+
+// `Hello, world!\n` compressed in each of the formats
+const GZIP_SAMPLE: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+    0x51, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0xe4, 0x02, 0x00, 0x18, 0xa7, 0x55, 0x7b, 0x0e, 0x00,
+    0x00, 0x00,
+];
+const BZIP2_SAMPLE: &[u8] = &[
+    0x42, 0x5a, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0x51, 0x88, 0xd0, 0x79, 0x00, 0x00,
+    0x02, 0x55, 0x80, 0x00, 0x10, 0x60, 0x04, 0x00, 0x40, 0x06, 0x04, 0x90, 0x80, 0x20, 0x00, 0x22,
+    0x06, 0x83, 0x20, 0x80, 0x69, 0xa6, 0x89, 0x16, 0x68, 0xea, 0x41, 0xbb, 0x3b, 0xc5, 0xdc, 0x91,
+    0x4e, 0x14, 0x24, 0x14, 0x62, 0x34, 0x1e, 0x40,
+];
+const XZ_SAMPLE: &[u8] = &[
+    0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00, 0x01, 0x69, 0x22, 0xde, 0x36, 0x02, 0x00, 0x21, 0x01,
+    0x16, 0x00, 0x00, 0x00, 0x74, 0x2f, 0xe5, 0xa3, 0x01, 0x00, 0x0d, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+    0x2c, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x21, 0x0a, 0x00, 0x00, 0x00, 0x18, 0xa7, 0x55, 0x7b,
+    0x00, 0x01, 0x22, 0x0e, 0x0c, 0xde, 0x8c, 0x60, 0x90, 0x42, 0x99, 0x0d, 0x01, 0x00, 0x00, 0x00,
+    0x00, 0x01, 0x59, 0x5a,
+];
+const ZSTD_SAMPLE: &[u8] = &[
+    0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x58, 0x71, 0x00, 0x00, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20,
+    0x77, 0x6f, 0x72, 0x6c, 0x64, 0x21, 0x0a,
+];
+const LZIP_SAMPLE: &[u8] = &[
+    0x4c, 0x5a, 0x49, 0x50, 0x01, 0x0c, 0x00, 0x24, 0x19, 0x49, 0x98, 0x6f, 0x16, 0x02, 0x8c, 0xe8,
+    0xe6, 0x5b, 0xb1, 0x47, 0xc5, 0x0d, 0xaf, 0x9d, 0xe5, 0xff, 0xff, 0x95, 0x80, 0x00, 0x00, 0x18,
+    0xa7, 0x55, 0x7b, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00,
+];
+
+impl Capabilities {
+    /// Returns the features of the linked `libmagic`
+    ///
+    /// The decompressors are found by looking into small samples with a new cookie and
+    /// the default database from `Magic::load_default()`, which has to be available.
+    pub fn probe() -> Result<Capabilities, FileMagicError> {
+        Magic::open(Flags::NONE)?.load_default()?.capabilities()
+    }
+}
+
+impl Magic<'_> {
+    /// Returns the features of the linked `libmagic`
+    ///
+    /// The decompressors are found by looking into small samples with this cookie, so
+    /// a database has to be loaded. libmagic older than 5.38 may fall back to external
+    /// programs for some formats, which then count as available too. The flags of the
+    /// cookie are the same afterwards.
+    pub fn capabilities(&self) -> Result<Capabilities, FileMagicError> {
+        let linked = version::libmagic_version_number();
+        let flags = self.flags();
+
+        let no_fork = Flags::NO_COMPRESS_FORK - Flags::NO_COMPRESS_FORK.unsupported_by(linked);
+        self.set_flags(Flags::COMPRESS | Flags::ERROR | no_fork)?;
+        let decompresses = |sample: &[u8]| {
+            self.buffer(sample)
+                .map(|description| description.starts_with("ASCII text"))
+        };
+        let decompressors = (|| {
+            Ok(Decompressors {
+                zlib: decompresses(GZIP_SAMPLE)?,
+                bzip2: decompresses(BZIP2_SAMPLE)?,
+                xz: decompresses(XZ_SAMPLE)?,
+                zstd: decompresses(ZSTD_SAMPLE)?,
+                lzip: decompresses(LZIP_SAMPLE)?,
+            })
+        })();
+        self.set_flags(flags)?;
+
+        Ok(Capabilities {
+            version: version::libmagic_version(),
+            flags: Flags::all() - Flags::all().unsupported_by(linked),
+            params: Param::ALL
+                .iter()
+                .copied()
+                .filter(|param| linked >= param.min_version())
+                .collect(),
+            decompressors: decompressors?,
+        })
+    }
+}
@@ -0,0 +1,96 @@
+//! Capturing what libmagic prints to the standard streams
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom},
+    os::fd::AsRawFd,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use libc::c_int;
+
+/// The streams are shared by the whole process, so only one may be captured at once
+static CAPTURE: Mutex<()> = Mutex::new(());
+
+/// Tells apart the capture files of this process
+static CAPTURE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs `call` with the streams `fds` redirected to a temporary file, and returns what
+/// was printed to them
+///
+/// Anything other threads print to the streams meanwhile is captured too. If a stream
+/// can't be redirected, `call` still runs and prints to the streams as usual.
+pub(crate) fn capture<T, F: FnOnce() -> T>(fds: &[c_int], call: F) -> (T, io::Result<Vec<u8>>) {
+    let mut file = match anonymous_file() {
+        Ok(file) => file,
+        Err(err) => return (call(), Err(err)),
+    };
+
+    let _guard = CAPTURE.lock().unwrap_or_else(|err| err.into_inner());
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+    let mut saved = Vec::with_capacity(fds.len());
+    for &fd in fds {
+        match redirect(fd, &file) {
+            Ok(original) => saved.push((fd, original)),
+            Err(err) => {
+                restore(&saved);
+                return (call(), Err(err));
+            }
+        }
+    }
+    let ret = call();
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+    restore(&saved);
+
+    let mut output = Vec::new();
+    let read = file
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_end(&mut output));
+    (ret, read.map(|_| output))
+}
+
+/// Creates a temporary file that is unlinked right away, so that it goes away with its
+/// last descriptor
+pub(crate) fn anonymous_file() -> io::Result<File> {
+    let path = std::env::temp_dir().join(format!(
+        "filemagic-capture-{}-{}",
+        process::id(),
+        CAPTURE_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let _ = fs::remove_file(&path);
+    Ok(file)
+}
+
+/// Points the stream `fd` to `file`, and returns a duplicate of what it pointed to
+fn redirect(fd: c_int, file: &File) -> io::Result<c_int> {
+    unsafe {
+        let saved = libc::dup(fd);
+        if saved == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::dup2(file.as_raw_fd(), fd) == -1 {
+            let err = io::Error::last_os_error();
+            libc::close(saved);
+            return Err(err);
+        }
+        Ok(saved)
+    }
+}
+
+/// Points the streams back to what `redirect()` saved
+fn restore(saved: &[(c_int, c_int)]) {
+    for &(fd, original) in saved {
+        unsafe {
+            libc::dup2(original, fd);
+            libc::close(original);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Running libmagic calls in a child process, away from the state of this one
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    os::fd::AsRawFd,
+};
+
+use libc::c_int;
+
+use crate::{api, capture::anonymous_file};
+
+/// What a libmagic call in a child process did
+pub(crate) struct Outcome {
+    /// The last error of the cookie and its errno, if the call failed
+    pub(crate) error: Option<(String, Option<i32>)>,
+    /// What the call printed to the streams passed to `run()`
+    pub(crate) printed: Vec<u8>,
+}
+
+/// Forks, and runs `call` on `cookie` in the child with the streams `fds` going to a
+/// temporary file and `dir` as the current directory, if any
+///
+/// Nothing `call` does to the cookie, the streams or the current directory reaches this
+/// process. The child only runs libmagic before it exits, which relies on the C library
+/// making `malloc()` and stdio usable after forking a process with several threads, as
+/// glibc, musl and the BSDs do.
+pub(crate) fn run<F: FnOnce() -> c_int>(
+    cookie: *const api::Magic,
+    dir: Option<&CStr>,
+    fds: &[c_int],
+    call: F,
+) -> io::Result<Outcome> {
+    let mut printed = anonymous_file()?;
+    let mut report = anonymous_file()?;
+
+    // so that the child doesn't print what this process buffered
+    unsafe { libc::fflush(std::ptr::null_mut()) };
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid == 0 {
+        unsafe { child(cookie, dir, fds, call, &printed, &report) }
+    }
+
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    if !libc::WIFEXITED(status) || libc::WEXITSTATUS(status) != 0 {
+        return Err(io::Error::other("the libmagic child process failed"));
+    }
+
+    let mut output = Vec::new();
+    read_from_start(&mut printed, &mut output)?;
+    let mut reported = Vec::new();
+    read_from_start(&mut report, &mut reported)?;
+    parse_report(&reported, output)
+}
+
+/// Runs in the child: calls `call` and writes what it returned to `report`
+///
+/// The report holds the return value and errno as native integers, followed by the
+/// last error of the cookie if it has one.
+unsafe fn child<F: FnOnce() -> c_int>(
+    cookie: *const api::Magic,
+    dir: Option<&CStr>,
+    fds: &[c_int],
+    call: F,
+    printed: &File,
+    report: &File,
+) -> ! {
+    if let Some(dir) = dir {
+        if libc::chdir(dir.as_ptr()) == -1 {
+            libc::_exit(1);
+        }
+    }
+    for &fd in fds {
+        if libc::dup2(printed.as_raw_fd(), fd) == -1 {
+            libc::_exit(1);
+        }
+    }
+    let ret = call();
+    libc::fflush(std::ptr::null_mut());
+
+    let errno = api::magic_errno(cookie);
+    let error = api::magic_error(cookie);
+    let mut ok = write_all(report, &ret.to_ne_bytes()) && write_all(report, &errno.to_ne_bytes());
+    if ok && !error.is_null() {
+        ok = write_all(report, CStr::from_ptr(error).to_bytes());
+    }
+    libc::_exit(if ok { 0 } else { 1 })
+}
+
+/// Writes `bytes` with `write()` itself, as little else is safe in the child
+unsafe fn write_all(file: &File, mut bytes: &[u8]) -> bool {
+    while !bytes.is_empty() {
+        let written = libc::write(file.as_raw_fd(), bytes.as_ptr().cast(), bytes.len());
+        if written <= 0 {
+            return false;
+        }
+        bytes = &bytes[written as usize..];
+    }
+    true
+}
+
+fn read_from_start(file: &mut File, buffer: &mut Vec<u8>) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(buffer).map(|_| ())
+}
+
+fn parse_report(report: &[u8], printed: Vec<u8>) -> io::Result<Outcome> {
+    const INT: usize = std::mem::size_of::<c_int>();
+    if report.len() < 2 * INT {
+        return Err(io::Error::other(
+            "the libmagic child process reported nothing",
+        ));
+    }
+    let ret = c_int::from_ne_bytes(report[..INT].try_into().unwrap());
+    let errno = c_int::from_ne_bytes(report[INT..2 * INT].try_into().unwrap());
+    let error = (ret != 0).then(|| {
+        let desc = match &report[2 * INT..] {
+            [] => "unknown error".to_string(),
+            desc => String::from_utf8_lossy(desc).into_owned(),
+        };
+        (desc, Some(errno).filter(|&errno| errno != 0))
+    });
+    Ok(Outcome { error, printed })
+}
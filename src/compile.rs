@@ -0,0 +1,149 @@
+//! Compiling magic databases somewhere else than the current directory
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{ErrorKind, FileMagicError};
+
+/// A source of magic rules to compile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source<'a> {
+    /// A file with magic rules, like those in `file/magic/Magdir`, or a directory of them
+    File(&'a Path),
+    /// Magic rules as text, in the same format as a file
+    Rules(&'a str),
+}
+
+impl<'a> Source<'a> {
+    /// Returns the source for the file or directory with magic rules at `path`
+    pub fn file<P: AsRef<Path> + ?Sized>(path: &'a P) -> Source<'a> {
+        Source::File(path.as_ref())
+    }
+
+    /// Returns the source for the magic rules in `rules`
+    pub fn rules(rules: &'a str) -> Source<'a> {
+        Source::Rules(rules)
+    }
+}
+
+impl<'a> From<&'a Path> for Source<'a> {
+    fn from(path: &'a Path) -> Source<'a> {
+        Source::File(path)
+    }
+}
+
+impl<'a> From<&'a PathBuf> for Source<'a> {
+    fn from(path: &'a PathBuf) -> Source<'a> {
+        Source::File(path)
+    }
+}
+
+/// Tells apart the staging directories of concurrent compilations in this process
+static STAGING_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory holding the sources of one compilation
+///
+/// libmagic always writes a compiled database into the current directory, named after
+/// the source. Compiling this directory writes `<name>.mgc`, where the name is unique
+/// to the process and the compilation. On Unix that happens in a child process whose
+/// current directory is this one, elsewhere in the current directory of the process.
+/// Both are removed when this is dropped.
+pub(crate) struct Staging {
+    dir: PathBuf,
+    output: PathBuf,
+}
+
+impl Staging {
+    /// Copies the `sources` into a new staging directory
+    pub(crate) fn new(sources: &[Source]) -> Result<Staging, FileMagicError> {
+        let name = format!(
+            "filemagic-{}-{}",
+            process::id(),
+            STAGING_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = std::env::temp_dir().join(&name);
+        fs::create_dir(&dir).map_err(|err| staging_error(err, "create", &dir))?;
+        #[cfg(unix)]
+        let output = dir.join(format!("{}.mgc", name));
+        #[cfg(not(unix))]
+        let output = PathBuf::from(format!("{}.mgc", name));
+        let staging = Staging { dir, output };
+
+        // libmagic reads the files of a directory sorted by name
+        let mut staged = 0;
+        let mut next_path = || {
+            staged += 1;
+            staging.dir.join(format!("{:06}", staged))
+        };
+        for source in sources {
+            match source {
+                Source::File(file) => {
+                    for file in source_files(file)? {
+                        fs::copy(&file, next_path())
+                            .map_err(|err| staging_error(err, "read", &file))?;
+                    }
+                }
+                Source::Rules(rules) => {
+                    let path = next_path();
+                    fs::write(&path, rules).map_err(|err| staging_error(err, "write", &path))?
+                }
+            }
+        }
+        Ok(staging)
+    }
+
+    /// Returns the directory to pass to `magic_compile()`
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Moves the compiled database to `path`
+    pub(crate) fn persist(&self, path: &Path) -> Result<(), FileMagicError> {
+        if fs::rename(&self.output, path).is_err() {
+            // across file systems
+            fs::copy(&self.output, path).map_err(|err| staging_error(err, "write", path))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the contents of the compiled database
+    pub(crate) fn read(&self) -> Result<Vec<u8>, FileMagicError> {
+        fs::read(&self.output).map_err(|err| staging_error(err, "read", &self.output))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.output);
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns the regular files in the directory `path` in the order libmagic reads them,
+/// or `path` itself if it isn't a directory
+fn source_files(path: &Path) -> Result<Vec<PathBuf>, FileMagicError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).map_err(|err| staging_error(err, "read", path))? {
+        let entry = entry.map_err(|err| staging_error(err, "read", path))?;
+        if entry.path().is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn staging_error(err: io::Error, action: &str, path: &Path) -> FileMagicError {
+    FileMagicError::new(
+        ErrorKind::Io(err.kind()),
+        format!("cannot {} `{}' ({})", action, path.display(), err),
+    )
+    .with_errno(err.raw_os_error())
+    .with_path(Some(path))
+}
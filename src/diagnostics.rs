@@ -0,0 +1,100 @@
+//! Warnings and traces libmagic would otherwise print to stderr
+use std::path::PathBuf;
+
+/// How bad a `Diagnostic` is
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Severity {
+    /// libmagic skipped or worked around something in a database
+    Warning,
+    /// The databases could not be used
+    Error,
+}
+
+/// A warning or error libmagic reported about a database
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Diagnostic {
+    /// Whether this is a warning or an error
+    pub severity: Severity,
+    /// The database file involved, if libmagic named it
+    pub file: Option<PathBuf>,
+    /// The line in `file` involved, if libmagic named it
+    pub line: Option<u64>,
+    /// What libmagic reported, like `` type `bogus' invalid ``
+    pub message: String,
+}
+
+/// Everything libmagic reported while checking databases with `Magic::check_report()`
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct CheckReport {
+    /// The warnings and errors, in the order libmagic reported them
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    /// Returns whether there are no errors, even though there may be warnings
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Returns the warnings
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.with_severity(Severity::Warning)
+    }
+
+    /// Returns the errors
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.with_severity(Severity::Error)
+    }
+
+    fn with_severity(&self, severity: Severity) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(move |diagnostic| diagnostic.severity == severity)
+    }
+}
+
+/// Receives the lines libmagic prints with `Flags::DEBUG`, see `Magic::set_debug_handler()`
+pub type DebugHandler = Box<dyn FnMut(&str) + Send>;
+
+/// Parses the warnings libmagic prints, like `db, 2: Warning: type `bogus' invalid`
+///
+/// Other lines, like the entries dumped with `Flags::CHECK`, are skipped.
+pub(crate) fn parse_warnings(output: &str) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let at = line.find("Warning: ")?;
+            let (file, line_number) = match line[..at].strip_suffix(": ") {
+                Some(location) => match location.rsplit_once(", ") {
+                    Some((file, number)) => (Some(PathBuf::from(file)), number.parse().ok()),
+                    None => (None, None),
+                },
+                None => (None, None),
+            };
+            Some(Diagnostic {
+                severity: Severity::Warning,
+                file,
+                line: line_number,
+                message: line[at + "Warning: ".len()..].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Turns the last error of libmagic into a `Diagnostic`, reading a `line N:` prefix
+pub(crate) fn error(desc: &str, file: Option<PathBuf>) -> Diagnostic {
+    let located = desc
+        .strip_prefix("line ")
+        .and_then(|rest| rest.split_once(':'))
+        .and_then(|(number, message)| Some((number.parse().ok()?, message.trim_start())));
+    let (line, message) = match located {
+        Some((line, message)) => (Some(line), message),
+        None => (None, desc),
+    };
+    Diagnostic {
+        severity: Severity::Error,
+        file,
+        line,
+        message: message.to_string(),
+    }
+}
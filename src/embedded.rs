@@ -0,0 +1,14 @@
+/// The default magic database compiled from the vendored `file/magic/Magdir` sources
+///
+/// libmagic reads the entries of a loaded database in place, so keep the bytes
+/// aligned like a heap allocation would be.
+#[repr(C, align(8))]
+struct Aligned<T: ?Sized>(T);
+
+static EMBEDDED_DB: &Aligned<[u8]> =
+    &Aligned(*include_bytes!(concat!(env!("OUT_DIR"), "/magic.mgc")));
+
+/// Returns the compiled default database embedded in this crate
+pub fn database() -> &'static [u8] {
+    &EMBEDDED_DB.0
+}
@@ -0,0 +1,96 @@
+/// The errors returned by this crate
+use std::{
+    error,
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The kind of a `FileMagicError`
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ErrorKind {
+    /// A cookie could not be created
+    Open,
+    /// A magic database could not be loaded, checked, compiled or listed
+    DatabaseLoad,
+    /// The data to look at could not be accessed
+    Io(io::ErrorKind),
+    /// Some flags are not supported by the linked `libmagic`
+    InvalidFlags,
+    /// A parameter is not supported by the linked `libmagic`, or can't take a value
+    InvalidParam,
+    /// A path can't be passed to `libmagic`
+    InvalidPath,
+    /// `libmagic` failed to look at the data for another reason
+    Detection,
+    /// Anything else, like a detection task that panicked
+    Other,
+}
+
+/// The error type used in this crate
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct FileMagicError {
+    /// What went wrong
+    pub kind: ErrorKind,
+    /// The error message, usually from `libmagic` itself
+    pub desc: String,
+    /// The `errno` reported along with the error, if any
+    pub errno: Option<i32>,
+    /// The file or database involved, if any
+    pub path: Option<PathBuf>,
+}
+
+impl FileMagicError {
+    pub(crate) fn new(kind: ErrorKind, desc: impl Into<String>) -> FileMagicError {
+        FileMagicError {
+            kind,
+            desc: desc.into(),
+            errno: None,
+            path: None,
+        }
+    }
+
+    pub(crate) fn with_errno(mut self, errno: Option<i32>) -> FileMagicError {
+        self.errno = errno;
+        self
+    }
+
+    pub(crate) fn with_path(mut self, path: Option<&Path>) -> FileMagicError {
+        self.path = path.map(Path::to_path_buf);
+        self
+    }
+
+    /// Wraps a failure to read data this crate reads itself
+    pub(crate) fn io(err: io::Error) -> FileMagicError {
+        FileMagicError::new(
+            ErrorKind::Io(err.kind()),
+            format!("cannot read from reader ({})", err),
+        )
+        .with_errno(err.raw_os_error())
+    }
+}
+
+impl error::Error for FileMagicError {
+    fn description(&self) -> &str {
+        "internal libmagic error"
+    }
+}
+
+impl Display for FileMagicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.desc)
+    }
+}
+
+impl From<FileMagicError> for io::Error {
+    fn from(err: FileMagicError) -> io::Error {
+        let kind = match err.kind {
+            ErrorKind::Io(kind) => kind,
+            ErrorKind::InvalidFlags | ErrorKind::InvalidParam | ErrorKind::InvalidPath => {
+                io::ErrorKind::InvalidInput
+            }
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
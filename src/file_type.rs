@@ -0,0 +1,33 @@
+//! Everything libmagic reports about some data at once
+use crate::apple::AppleType;
+
+/// The results of every output mode of libmagic for the same data
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct FileType {
+    /// The textual description, as returned without any output mode flag
+    pub description: String,
+    /// The MIME type, as returned with `Flags::MIME_TYPE`
+    pub mime_type: String,
+    /// The MIME encoding, as returned with `Flags::MIME_ENCODING`
+    pub mime_encoding: String,
+    /// The usual file extensions, preferred one first, as returned with `Flags::EXTENSION`
+    ///
+    /// Empty if libmagic doesn't know any.
+    pub extensions: Vec<String>,
+    /// The Apple creator and type, as returned with `Flags::APPLE`
+    ///
+    /// `None` if libmagic doesn't know them.
+    pub apple: Option<AppleType>,
+}
+
+/// Splits the output of `Flags::EXTENSION`, which is `???` when there are none
+pub(crate) fn parse_extensions(output: &str) -> Vec<String> {
+    if output == "???" {
+        return Vec::new();
+    }
+    output
+        .split('/')
+        .filter(|extension| !extension.is_empty())
+        .map(str::to_string)
+        .collect()
+}
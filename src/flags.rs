@@ -3,7 +3,7 @@ use libc::c_int;
 
 bitflags! {
     #[doc = "Bitmask flags that specify how `Cookie` functions should behave\n\nNOTE: The descriptions are taken from `man libmagic 3`."]
-    #[derive(Default)]
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct Flags: c_int {
         #[doc = "No special handling"]
         const NONE              = 0x000_0000;
@@ -54,6 +54,9 @@ bitflags! {
         #[doc = "Check inside compressed files but do not report compression"]
         const COMPRESS_TRANSP   = 0x200_0000;
 
+        #[doc = "Don't allow decompression that needs to fork"]
+        const NO_COMPRESS_FORK  = 0x400_0000;
+
         const MAGIC_NODESC = Self::EXTENSION.bits()
                             | Self::MIME.bits()
                             | Self::APPLE.bits();
@@ -113,3 +116,33 @@ bitflags! {
         const NO_CHECK_TROFF = 0x00_0000;
     }
 }
+
+impl Flags {
+    /// Returns the flags in `self` which the `libmagic` `version` (as returned by
+    /// `magic_version()`) doesn't know, including bits no flag is defined for
+    pub(crate) fn unsupported_by(self, version: c_int) -> Flags {
+        // flags added after the first releases of libmagic 5
+        const ADDED: &[(Flags, c_int)] = &[
+            (Flags::EXTENSION, 523),
+            (Flags::COMPRESS_TRANSP, 532),
+            (Flags::NO_CHECK_JSON, 535),
+            (Flags::NO_CHECK_CSV, 538),
+            (Flags::NO_COMPRESS_FORK, 538),
+        ];
+
+        let mut unsupported = Flags::from_bits_retain(self.bits() & !Flags::all().bits());
+        for &(flag, added) in ADDED {
+            if version < added && self.contains(flag) {
+                unsupported |= flag;
+            }
+        }
+        unsupported
+    }
+
+    /// Returns the names of the flags in `self`, like `EXTENSION | 0x40000000`
+    pub(crate) fn names(self) -> String {
+        let mut names = String::new();
+        bitflags::parser::to_writer(&self, &mut names).unwrap();
+        names
+    }
+}
@@ -15,8 +15,9 @@
 //! use filemagic::Magic;
 //!
 //! let test_file = "path/to/file";
-//! let cookie = Magic::open(Default::default()).expect("error");
-//! cookie.load::<String>(&[]).expect("error");
+//! let cookie = Magic::open(Default::default())
+//!     .and_then(|cookie| cookie.load::<String>(&[]))
+//!     .expect("error");
 //! let magic = cookie.file(&test_file).expect("error in magic");
 //! println!("magic= {}", magic);
 //! ```
@@ -27,92 +28,341 @@ extern crate bitflags;
 pub mod macros;
 
 extern crate libc;
-use libc::{c_char, size_t};
+use libc::{c_char, c_int, c_void, size_t};
 
 mod api;
+#[cfg(unix)]
+mod capture;
+#[cfg(unix)]
+mod child;
+mod snapshot;
+use snapshot::Snapshot;
 
 pub mod version;
-pub use version::version;
+pub use version::{libmagic_version, version};
+
+pub mod capabilities;
+pub use capabilities::{Capabilities, Decompressors};
+
+pub mod error;
+pub use error::{ErrorKind, FileMagicError};
 
 pub mod flags;
 pub use flags::Flags;
 
+pub mod param;
+pub use param::Param;
+
+pub mod state;
+pub use state::{Loaded, State, Unloaded};
+
+pub mod apple;
+pub use apple::AppleType;
+
+pub mod diagnostics;
+pub use diagnostics::{CheckReport, DebugHandler, Diagnostic, Severity};
+
+pub mod file_type;
+pub use file_type::FileType;
+
+pub mod matches;
+pub use matches::Match;
+
+pub mod mime;
+pub use crate::mime::Mime;
+
+pub mod builder;
+pub use builder::MagicBuilder;
+
+pub mod compile;
+pub use compile::Source;
+use compile::Staging;
+
+#[cfg(unix)]
+pub mod list;
+#[cfg(unix)]
+pub use list::MagicEntry;
+
+pub mod pool;
+pub use pool::{MagicPool, PooledMagic};
+
+pub mod scan;
+pub use scan::Scanner;
+
+pub mod cache;
+pub use cache::{CachedMagic, Eviction};
+
+#[cfg(feature = "embedded-db")]
+pub mod embedded;
+
+#[cfg(feature = "tokio")]
+pub mod async_magic;
+#[cfg(feature = "tokio")]
+pub use async_magic::AsyncMagic;
+
 #[cfg(test)]
 mod tests;
 
 use std::{
-    error,
+    cell::Cell,
     ffi::{CStr, CString},
-    fmt::Display,
+    io::{self, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    mem::ManuallyDrop,
     path::Path,
-    ptr, str,
+    ptr,
+};
+
+#[cfg(unix)]
+use std::{
+    fs::File,
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::ffi::OsStrExt,
+    },
 };
 
-fn db_filenames<P: AsRef<Path>>(filenames: &[P]) -> *const c_char {
-    match filenames.len() {
-        0 => ptr::null(),
-        // FIXME: This is just plain wrong. I'm surprised it works at all..
-        1 => CString::new(filenames[0].as_ref().to_string_lossy().into_owned())
-            .unwrap()
-            .into_raw(),
-        _ => unimplemented!(),
+/// Separator libmagic uses between entries of a database list
+#[cfg(windows)]
+const PATH_SEPARATOR: char = ';';
+#[cfg(not(windows))]
+const PATH_SEPARATOR: char = ':';
+
+/// Joins the database `filenames` into the separated list libmagic expects
+///
+/// Returns `None` for an empty list, which makes libmagic use its default database.
+fn db_filenames<P: AsRef<Path>>(filenames: &[P]) -> Result<Option<CString>, FileMagicError> {
+    if filenames.is_empty() {
+        return Ok(None);
+    }
+
+    let mut joined = String::new();
+    for (i, filename) in filenames.iter().enumerate() {
+        let path = filename.as_ref();
+        let name = path.to_str().ok_or_else(|| {
+            FileMagicError::new(
+                ErrorKind::InvalidPath,
+                format!("database path `{}' is not valid UTF-8", path.display()),
+            )
+            .with_path(Some(path))
+        })?;
+        if name.contains(PATH_SEPARATOR) {
+            return Err(FileMagicError::new(
+                ErrorKind::InvalidPath,
+                format!(
+                    "database path `{}' contains the separator `{}'",
+                    name, PATH_SEPARATOR
+                ),
+            )
+            .with_path(Some(path)));
+        }
+        if i > 0 {
+            joined.push(PATH_SEPARATOR);
+        }
+        joined.push_str(name);
     }
+
+    CString::new(joined).map(Some).map_err(|_| {
+        FileMagicError::new(ErrorKind::InvalidPath, "database path contains a NUL byte")
+    })
 }
 
-/// The error type used in this crate
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub struct FileMagicError {
-    pub desc: String,
+/// Returns the pointer to pass to libmagic for an optional database list
+fn db_filenames_ptr(filenames: &Option<CString>) -> *const c_char {
+    filenames.as_ref().map_or(ptr::null(), |f| f.as_ptr())
 }
 
-impl error::Error for FileMagicError {
-    fn description(&self) -> &str {
-        "internal libmagic error"
-    }
+/// Converts `path` into the C string libmagic expects
+///
+/// On Unix the bytes of the path are passed as they are, elsewhere it has to be
+/// valid UTF-8.
+fn path_cstring(path: &Path) -> Result<CString, FileMagicError> {
+    #[cfg(unix)]
+    let bytes = path.as_os_str().as_bytes();
+    #[cfg(not(unix))]
+    let bytes = path
+        .to_str()
+        .ok_or_else(|| {
+            FileMagicError::new(
+                ErrorKind::InvalidPath,
+                format!("path `{}' is not valid UTF-8", path.display()),
+            )
+            .with_path(Some(path))
+        })?
+        .as_bytes();
+    CString::new(bytes).map_err(|_| {
+        FileMagicError::new(
+            ErrorKind::InvalidPath,
+            format!("path `{}' contains a NUL byte", path.display()),
+        )
+        .with_path(Some(path))
+    })
+}
+
+/// Converts the output of libmagic into a `String`, replacing invalid UTF-8
+fn lossy(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+}
+
+/// Wraps the `error` libmagic reported for the database `filenames`, with its errno
+fn database_error<P: AsRef<Path>>(
+    (desc, errno): (String, Option<i32>),
+    filenames: &[P],
+) -> FileMagicError {
+    let path = match filenames {
+        [filename] => Some(filename.as_ref()),
+        _ => None,
+    };
+    FileMagicError::new(ErrorKind::DatabaseLoad, desc)
+        .with_errno(errno)
+        .with_path(path)
+}
+
+/// Wraps a failure to run libmagic in a child process
+#[cfg(unix)]
+fn child_failure(action: &str, err: io::Error) -> FileMagicError {
+    FileMagicError::new(
+        ErrorKind::Io(err.kind()),
+        format!("cannot {} in a child process ({})", action, err),
+    )
+    .with_errno(err.raw_os_error())
 }
 
-impl Display for FileMagicError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.desc)
+/// Fails if the linked libmagic doesn't support some of the `flags`
+fn check_flags(flags: Flags) -> Result<(), FileMagicError> {
+    let linked = version::libmagic_version_number();
+    let unsupported = flags.unsupported_by(linked);
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(FileMagicError::new(
+            ErrorKind::InvalidFlags,
+            format!(
+                "flags `{}' are not supported by libmagic {}.{}",
+                unsupported.names(),
+                linked / 100,
+                linked % 100
+            ),
+        ))
     }
 }
 
+/// The first libmagic version with `magic_getflags()`
+const MAGIC_GETFLAGS_VERSION: c_int = 532;
+
+/// How many bytes libmagic looks at when it can't tell otherwise
+const DEFAULT_BYTES_MAX: usize = 7 * 1024 * 1024;
+
 /// Configuration of which `Flags` and magic databases to use
-pub struct Magic {
+///
+/// The `'db` lifetime covers databases loaded from memory with `load_buffers()`,
+/// which libmagic keeps referencing for as long as the cookie is alive. The state `S`
+/// is `Unloaded` after `open()` and `Loaded` once a database is loaded, which is when
+/// the cookie can look at data.
+pub struct Magic<'db, S: State = Loaded> {
     magic: *const api::Magic,
+    // for libmagic versions without `magic_getflags()`
+    flags: Cell<Flags>,
+    // invariant, so that a cookie can't be shortened to accept shorter-lived buffers
+    databases: PhantomData<Cell<&'db [u8]>>,
+    state: PhantomData<S>,
+    // taken out while it runs, so that it may use the cookie
+    debug: Cell<Option<DebugHandler>>,
+    // tells apart the loaded databases, for `CachedMagic`
+    database: u64,
 }
 
-impl Drop for Magic {
+// libmagic keeps no thread affinity for a cookie, it just can't be used from two
+// threads at once, which `Magic` not being `Sync` already rules out
+unsafe impl<S: State> Send for Magic<'_, S> {}
+
+impl<S: State> Drop for Magic<'_, S> {
     /// Closes the magic database and deallocates any resources used
     fn drop(&mut self) {
         unsafe { api::magic_close(self.magic) }
     }
 }
 
-impl Magic {
-    fn last_error(&self) -> Option<FileMagicError> {
+impl<'db, S: State> Magic<'db, S> {
+    /// Hands the cookie over to the state `T` without closing it
+    fn into_state<T: State>(self) -> Magic<'db, T> {
+        let cookie = ManuallyDrop::new(self);
+        Magic {
+            magic: cookie.magic,
+            flags: Cell::new(cookie.flags.get()),
+            databases: PhantomData,
+            state: PhantomData,
+            debug: Cell::new(cookie.debug.take()),
+            database: cookie.database,
+        }
+    }
+
+    /// Identifies the loaded databases, as far as telling them apart goes
+    pub(crate) fn database_id(&self) -> u64 {
+        self.database
+    }
+
+    fn last_error(&self) -> (String, Option<i32>) {
         let cookie = self.magic;
 
         unsafe {
             let e = api::magic_error(cookie);
-            if e.is_null() {
-                None
+            let desc = if e.is_null() {
+                "unknown error".to_string()
             } else {
-                let slice = CStr::from_ptr(e).to_bytes();
-                Some(self::FileMagicError {
-                    desc: str::from_utf8(slice).unwrap().to_string(),
-                })
-            }
+                CStr::from_ptr(e).to_string_lossy().into_owned()
+            };
+            let errno = match api::magic_errno(cookie) {
+                0 => None,
+                errno => Some(errno),
+            };
+            (desc, errno)
+        }
+    }
+
+    /// Returns the last error of libmagic while looking at the data in `path`, if any
+    fn magic_failure(&self, path: Option<&Path>) -> FileMagicError {
+        let (desc, errno) = self.last_error();
+        let kind = match errno {
+            Some(errno) => ErrorKind::Io(io::Error::from_raw_os_error(errno).kind()),
+            None => ErrorKind::Detection,
+        };
+        FileMagicError::new(kind, desc)
+            .with_errno(errno)
+            .with_path(path)
+    }
+
+    /// Returns the last error of libmagic while handling the database `filenames`
+    fn database_failure<P: AsRef<Path>>(&self, filenames: &[P]) -> FileMagicError {
+        database_error(self.last_error(), filenames)
+    }
+
+    /// Returns how many bytes of a file libmagic looks at
+    fn bytes_max(&self) -> usize {
+        match self.get_param(Param::BytesMax) {
+            Ok(value) if value > 0 => value,
+            _ => DEFAULT_BYTES_MAX,
         }
     }
 
-    fn magic_failure(&self) -> FileMagicError {
-        match self.last_error() {
-            Some(e) => e,
-            None => self::FileMagicError {
-                desc: "unknown error".to_string(),
-            },
+    /// Fails if the linked libmagic doesn't know `param`
+    fn check_param(param: Param) -> Result<(), FileMagicError> {
+        let linked = version::libmagic_version_number();
+        if linked < param.min_version() {
+            return Err(FileMagicError::new(
+                ErrorKind::InvalidParam,
+                format!(
+                    "parameter `{}' needs libmagic {}.{}, but {}.{} is linked",
+                    param.name(),
+                    param.min_version() / 100,
+                    param.min_version() % 100,
+                    linked / 100,
+                    linked % 100
+                ),
+            ));
         }
+        Ok(())
     }
 
     /// Returns a textual explanation of the last error, if any
@@ -126,133 +376,751 @@ impl Magic {
             if str.is_null() {
                 None
             } else {
-                let slice = CStr::from_ptr(str).to_bytes();
-                Some(str::from_utf8(slice).unwrap().to_string())
+                Some(CStr::from_ptr(str).to_string_lossy().into_owned())
             }
         }
     }
 
-    /// Returns a textual description of the contents of the `filename`
-    pub fn file<P: AsRef<Path>>(&self, filename: P) -> Result<String, FileMagicError> {
-        let cookie = self.magic;
-        let f = CString::new(filename.as_ref().to_string_lossy().into_owned())
-            .unwrap()
-            .into_raw();
-        unsafe {
-            let str = api::magic_file(cookie, f);
-            if str.is_null() {
-                Err(self.magic_failure())
+    /// Runs `detect` with the flags `mode`, then restores the flags of the cookie
+    fn with_mode<T, F: FnOnce(&Self) -> T>(&self, mode: Flags, detect: F) -> T {
+        let flags = self.flags();
+        unsafe { api::magic_setflags(self.magic, mode.bits()) };
+        let output = detect(self);
+        unsafe { api::magic_setflags(self.magic, flags.bits()) };
+        output
+    }
+
+    /// Runs the libmagic `call`, passing the lines it prints to the debug handler if
+    /// there is one and `Flags::DEBUG` is set
+    fn traced<T, F: FnOnce() -> T>(&self, call: F) -> T {
+        #[cfg(unix)]
+        if let Some(mut handler) = self.debug.take() {
+            let ret = if self.flags().contains(Flags::DEBUG) {
+                let (ret, output) = capture::capture(&[libc::STDERR_FILENO], call);
+                if let Ok(output) = output {
+                    lossy(output).lines().for_each(&mut handler);
+                }
+                ret
             } else {
-                let slice = CStr::from_ptr(str).to_bytes();
-                Ok(str::from_utf8(slice).unwrap().to_string())
+                call()
+            };
+            self.debug.set(Some(handler));
+            return ret;
+        }
+        call()
+    }
+
+    /// Sets the `handler` for the traces libmagic prints with `Flags::DEBUG` while looking
+    /// at data, or removes it with `None`
+    ///
+    /// Without a handler, the traces go to stderr. With one, stderr is redirected to a
+    /// temporary file during each detection with `Flags::DEBUG`, which only works on Unix.
+    /// stderr belongs to the whole process, so anything other threads print to it
+    /// meanwhile goes to the handler too, and such detections wait for each other across
+    /// all cookies of the process.
+    pub fn set_debug_handler(&self, handler: Option<DebugHandler>) {
+        self.debug.set(handler);
+    }
+
+    /// Sends the traces libmagic prints with `Flags::DEBUG` to the `log` crate
+    ///
+    /// Each line is logged at the debug level with the target `filemagic`. See
+    /// `set_debug_handler()` for how they are captured.
+    #[cfg(feature = "log")]
+    pub fn log_debug(&self) {
+        self.set_debug_handler(Some(Box::new(
+            |line: &str| log::debug!(target: "filemagic", "{}", line),
+        )));
+    }
+
+    /// Checks the database `filenames` and returns every warning and error libmagic reports
+    ///
+    /// `Flags::CHECK` is set meanwhile, so that libmagic reports questionable entries too.
+    /// libmagic checks in a child process whose stderr is captured, so the database of
+    /// this cookie and the stderr of this process are left alone. Fails only if that
+    /// doesn't work.
+    #[cfg(unix)]
+    pub fn check_report<P: AsRef<Path>>(
+        &self,
+        filenames: &[P],
+    ) -> Result<CheckReport, FileMagicError> {
+        let db_filenames = db_filenames(filenames)?;
+        let flags = self.flags() | Flags::CHECK;
+        let outcome = child::run(self.magic, None, &[libc::STDERR_FILENO], || unsafe {
+            api::magic_setflags(self.magic, flags.bits());
+            api::magic_check(self.magic, db_filenames_ptr(&db_filenames))
+        })
+        .map_err(|err| child_failure("check databases", err))?;
+
+        let mut diagnostics = diagnostics::parse_warnings(&lossy(outcome.printed));
+        if let Some((desc, _)) = outcome.error {
+            let file = match filenames {
+                [filename] => Some(filename.as_ref().to_path_buf()),
+                _ => None,
+            };
+            diagnostics.push(diagnostics::error(&desc, file));
+        }
+        Ok(CheckReport { diagnostics })
+    }
+
+    /// Compiles the `sources` into a single database at `output`
+    ///
+    /// libmagic can only write into the current directory, so on Unix it compiles in a
+    /// child process whose current directory is a temporary one, and the database is
+    /// moved to `output` from there. Elsewhere, it is created in the current directory
+    /// under a name unique to this call first. Either way, errors in the rules name the
+    /// staged copies of the sources in the temporary directory, and the cookie itself is
+    /// left alone.
+    pub fn compile_to<P: AsRef<Path>>(
+        &self,
+        sources: &[Source],
+        output: P,
+    ) -> Result<(), FileMagicError> {
+        self.compile_staged(sources)?.persist(output.as_ref())
+    }
+
+    /// Compiles the `sources` into a single database and returns its contents
+    ///
+    /// The result can be passed to `load_buffers()`. See `compile_to()` for where the
+    /// database is written meanwhile.
+    pub fn compile_to_vec(&self, sources: &[Source]) -> Result<Vec<u8>, FileMagicError> {
+        self.compile_staged(sources)?.read()
+    }
+
+    fn compile_staged(&self, sources: &[Source]) -> Result<Staging, FileMagicError> {
+        let staging = Staging::new(sources)?;
+        let dir = path_cstring(staging.dir())?;
+        // libmagic replaces the database of the cookie that compiles
+        let scratch = Magic::open(self.flags())?;
+
+        #[cfg(unix)]
+        let error = child::run(scratch.magic, Some(&dir), &[], || unsafe {
+            api::magic_compile(scratch.magic, dir.as_ptr())
+        })
+        .map_err(|err| child_failure("compile", err))?
+        .error;
+        #[cfg(not(unix))]
+        let error = match unsafe { api::magic_compile(scratch.magic, dir.as_ptr()) } {
+            0 => None,
+            _ => Some(scratch.last_error()),
+        };
+
+        match error {
+            None => Ok(staging),
+            Some(error) => {
+                let filenames = match sources {
+                    [Source::File(path)] => vec![*path],
+                    _ => vec![],
+                };
+                Err(database_error(error, &filenames))
             }
         }
     }
 
-    /// Returns a textual description of the contents of the `buffer`
-    pub fn buffer(&self, buffer: &[u8]) -> Result<String, FileMagicError> {
-        let buffer_len = buffer.len() as size_t;
-        let pbuffer = buffer.as_ptr();
-        unsafe {
-            let str = api::magic_buffer(self.magic, pbuffer, buffer_len);
-            if str.is_null() {
-                Err(self.magic_failure())
-            } else {
-                let slice = CStr::from_ptr(str).to_bytes();
-                Ok(str::from_utf8(slice).unwrap().to_string())
+    /// Returns the top-level tests in the given database `filenames`, in the order
+    /// libmagic tries them
+    ///
+    /// Each database is listed on its own, and for directories each file in them. The
+    /// offset, type, value and extensions of a test are read from its source file, so
+    /// they are missing for compiled databases.
+    ///
+    /// libmagic lists with a cookie of its own in a child process, so the database of
+    /// this cookie and the stdout of this process are left alone.
+    #[cfg(unix)]
+    pub fn list_entries<P: AsRef<Path>>(
+        &self,
+        filenames: &[P],
+    ) -> Result<Vec<list::MagicEntry>, FileMagicError> {
+        if filenames.is_empty() {
+            return self.list_file::<&Path>(None);
+        }
+        let mut entries = Vec::new();
+        for filename in list::database_files(filenames) {
+            entries.extend(self.list_file(Some(&filename))?);
+        }
+        Ok(entries)
+    }
+
+    #[cfg(unix)]
+    fn list_file<P: AsRef<Path>>(
+        &self,
+        filename: Option<P>,
+    ) -> Result<Vec<list::MagicEntry>, FileMagicError> {
+        let filenames: Vec<P> = filename.into_iter().collect();
+        let db_filenames = db_filenames(&filenames)?;
+        // libmagic replaces the database of the cookie that lists, and prints to stdout
+        let scratch = Magic::open(self.flags())?;
+        let outcome = child::run(scratch.magic, None, &[libc::STDOUT_FILENO], || unsafe {
+            api::magic_list(scratch.magic, db_filenames_ptr(&db_filenames))
+        })
+        .map_err(|err| child_failure("list entries", err))?;
+        if let Some(error) = outcome.error {
+            return Err(database_error(error, &filenames));
+        }
+
+        let mut entries = list::parse_list(&lossy(outcome.printed));
+        if let [filename] = filenames.as_slice() {
+            match std::fs::read(filename) {
+                Ok(source) if !list::is_compiled(&source) => {
+                    list::annotate(&mut entries, &lossy(source))
+                }
+                _ => {}
             }
         }
+        Ok(entries)
+    }
+
+    /// Sets the flags to use
+    ///
+    /// Overwrites any previously set flags, e.g. those from `open()` or `load()`. Fails without
+    /// changing anything if the linked libmagic doesn't support some of the `flags`.
+    pub fn set_flags(&self, flags: Flags) -> Result<(), FileMagicError> {
+        check_flags(flags)?;
+        if unsafe { api::magic_setflags(self.magic, flags.bits()) } == -1 {
+            // only refused when `PRESERVE_ATIME` can't be honoured
+            return Err(FileMagicError::new(
+                ErrorKind::InvalidFlags,
+                format!(
+                    "flags `{}' are not supported on this system",
+                    (flags & Flags::PRESERVE_ATIME).names()
+                ),
+            ));
+        }
+        self.flags.set(flags);
+        Ok(())
+    }
+
+    /// Returns the flags currently in use
+    pub fn flags(&self) -> Flags {
+        if version::libmagic_version_number() >= MAGIC_GETFLAGS_VERSION {
+            Flags::from_bits_retain(unsafe { api::magic_getflags(self.magic) })
+        } else {
+            self.flags.get()
+        }
+    }
+
+    /// Sets the limit `param` to `value`
+    ///
+    /// Fails if the linked libmagic doesn't support `param`.
+    pub fn set_param(&self, param: Param, value: usize) -> Result<(), FileMagicError> {
+        Self::check_param(param)?;
+        let value = value as size_t;
+        let ret = unsafe {
+            api::magic_setparam(
+                self.magic,
+                param.raw(),
+                &value as *const size_t as *const c_void,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(FileMagicError::new(
+                ErrorKind::InvalidParam,
+                format!("cannot set parameter `{}' to {}", param.name(), value),
+            ))
+        }
+    }
+
+    /// Returns the current value of the limit `param`
+    ///
+    /// Fails if the linked libmagic doesn't support `param`.
+    pub fn get_param(&self, param: Param) -> Result<usize, FileMagicError> {
+        Self::check_param(param)?;
+        let mut value: size_t = 0;
+        let ret = unsafe {
+            api::magic_getparam(
+                self.magic,
+                param.raw(),
+                &mut value as *mut size_t as *mut c_void,
+            )
+        };
+        if ret == 0 {
+            Ok(value as usize)
+        } else {
+            Err(FileMagicError::new(
+                ErrorKind::InvalidParam,
+                format!("cannot get parameter `{}'", param.name()),
+            ))
+        }
+    }
+
+    /// Loads the default database for further queries
+    ///
+    /// This is the database embedded in this crate with the `embedded-db` feature,
+    /// and libmagic's own default database otherwise.
+    pub fn load_default(self) -> Result<Magic<'db, Loaded>, FileMagicError> {
+        #[cfg(feature = "embedded-db")]
+        return self.load_default_embedded();
+
+        #[cfg(not(feature = "embedded-db"))]
+        return self.load::<&str>(&[]);
+    }
+
+    /// Loads the database embedded in this crate for further queries
+    ///
+    /// Unlike `load()` with no filenames, this does not depend on any database
+    /// installed on the host.
+    #[cfg(feature = "embedded-db")]
+    pub fn load_default_embedded(self) -> Result<Magic<'db, Loaded>, FileMagicError> {
+        self.load_buffers(&[embedded::database()])
+    }
+
+    /// Loads the given database `filenames` for further queries
+    /// Adds '.mgc' to the database files as appropriate.
+    ///
+    /// Any database loaded before is replaced. libmagic drops it even when the new one
+    /// fails to load, so the cookie is closed on failure.
+    pub fn load<P: AsRef<Path>>(
+        self,
+        magic_databases: &[P],
+    ) -> Result<Magic<'db, Loaded>, FileMagicError> {
+        let cookie = self.magic;
+        let db_filenames = db_filenames(magic_databases)?;
+        let ret;
+
+        unsafe {
+            ret = api::magic_load(cookie, db_filenames_ptr(&db_filenames));
+        }
+        if 0 == ret {
+            let mut loaded = self.into_state();
+            loaded.database = cache::files_database_id(magic_databases);
+            Ok(loaded)
+        } else {
+            Err(self.database_failure(magic_databases))
+        }
+    }
+
+    /// Loads the given compiled databases `buffers` from memory for further queries
+    ///
+    /// Each buffer must hold the contents of a compiled `.mgc` file. libmagic does not
+    /// copy them, so they are borrowed for the lifetime of the cookie. As with `load()`,
+    /// the cookie is closed on failure.
+    pub fn load_buffers(self, buffers: &[&'db [u8]]) -> Result<Magic<'db, Loaded>, FileMagicError> {
+        let cookie = self.magic;
+        let pointers: Vec<*const u8> = buffers.iter().map(|b| b.as_ptr()).collect();
+        let sizes: Vec<size_t> = buffers.iter().map(|b| b.len() as size_t).collect();
+        let ret;
+
+        unsafe {
+            ret = api::magic_load_buffers(
+                cookie,
+                pointers.as_ptr(),
+                sizes.as_ptr(),
+                buffers.len() as size_t,
+            );
+        }
+        if 0 == ret {
+            let mut loaded = self.into_state();
+            loaded.database = cache::buffers_database_id(buffers);
+            Ok(loaded)
+        } else {
+            Err(self.database_failure::<&str>(&[]))
+        }
+    }
+}
+
+impl<'db> Magic<'db, Unloaded> {
+    /// Returns a `MagicBuilder` to create a cookie with its databases and parameters at once
+    pub fn builder() -> MagicBuilder<'db> {
+        MagicBuilder::new()
+    }
+
+    /// Creates a new configuration, `flags` specify how other functions should behave
+    ///
+    /// This does not `load()` any databases yet.
+    pub fn open(flags: Flags) -> Result<Magic<'db, Unloaded>, FileMagicError> {
+        let flags = flags | Flags::ERROR;
+        check_flags(flags)?;
+        let cookie;
+        unsafe {
+            cookie = api::magic_open(flags.bits());
+        }
+        if cookie.is_null() {
+            let err = io::Error::last_os_error();
+            Err(
+                FileMagicError::new(ErrorKind::Open, format!("cannot open cookie ({})", err))
+                    .with_errno(err.raw_os_error()),
+            )
+        } else {
+            Ok(Magic {
+                magic: cookie,
+                flags: Cell::new(flags),
+                databases: PhantomData,
+                state: PhantomData,
+                debug: Cell::new(None),
+                database: 0,
+            })
+        }
     }
 
     /// Check the validity of entries in the database `filenames`
+    ///
+    /// libmagic loads the databases into the cookie meanwhile, which is why this isn't
+    /// available once a database is loaded.
     pub fn check<P: AsRef<Path>>(&self, filenames: &[P]) -> Result<(), FileMagicError> {
         let cookie = self.magic;
-        let db_filenames = db_filenames(filenames);
+        let db_filenames = db_filenames(filenames)?;
         let ret;
 
         unsafe {
-            ret = api::magic_check(cookie, db_filenames);
+            ret = api::magic_check(cookie, db_filenames_ptr(&db_filenames));
         }
         if 0 == ret {
             Ok(())
         } else {
-            Err(self.magic_failure())
+            Err(self.database_failure(filenames))
         }
     }
 
     /// Compiles the given database `filenames` for faster access
     ///
     /// The compiled files created are named from the `basename` of each file argument with '.mgc' appended to it.
+    /// As with `check()`, libmagic loads the databases into the cookie meanwhile.
     pub fn compile<P: AsRef<Path>>(&self, filenames: &[P]) -> Result<(), FileMagicError> {
         let cookie = self.magic;
-        let db_filenames = db_filenames(filenames);
+        let db_filenames = db_filenames(filenames)?;
         let ret;
 
         unsafe {
-            ret = api::magic_compile(cookie, db_filenames);
+            ret = api::magic_compile(cookie, db_filenames_ptr(&db_filenames));
         }
         if 0 == ret {
             Ok(())
         } else {
-            Err(self.magic_failure())
+            Err(self.database_failure(filenames))
         }
     }
 
     /// Dumps all magic entries in the given database `filenames` in a human readable format
+    ///
+    /// As with `check()`, libmagic loads the databases into the cookie meanwhile.
     pub fn list<P: AsRef<Path>>(&self, filenames: &[P]) -> Result<(), FileMagicError> {
         let cookie = self.magic;
-        let db_filenames = db_filenames(filenames);
+        let db_filenames = db_filenames(filenames)?;
         let ret;
 
         unsafe {
-            ret = api::magic_list(cookie, db_filenames);
+            ret = api::magic_list(cookie, db_filenames_ptr(&db_filenames));
         }
         if 0 == ret {
             Ok(())
         } else {
-            Err(self.magic_failure())
+            Err(self.database_failure(filenames))
         }
     }
+}
 
-    /// Sets the flags to use
+impl<'db> Magic<'db, Loaded> {
+    /// Copies the output `str` of libmagic, or returns its last error if there is none
+    unsafe fn output(
+        &self,
+        str: *const c_char,
+        path: Option<&Path>,
+    ) -> Result<Vec<u8>, FileMagicError> {
+        if str.is_null() {
+            Err(self.magic_failure(path))
+        } else {
+            Ok(CStr::from_ptr(str).to_bytes().to_vec())
+        }
+    }
+
+    /// Returns a textual description of the contents of the `filename`
     ///
-    /// Overwrites any previously set flags, e.g. those from `load()`.
-    // TODO: libmagic itself has to magic_getflags, but we could remember them in Cookie?
-    pub fn set_flags(&self, flags: Flags) -> bool {
-        unsafe { api::magic_setflags(self.magic, flags.bits()) != -1 }
+    /// Bytes of the description that are not valid UTF-8 are replaced with
+    /// `U+FFFD`; use `file_bytes()` to get them unchanged.
+    pub fn file<P: AsRef<Path>>(&self, filename: P) -> Result<String, FileMagicError> {
+        self.file_bytes(filename).map(lossy)
     }
 
-    /// Creates a new configuration, `flags` specify how other functions should behave
+    /// Returns the raw description of the contents of the `filename`
     ///
-    /// This does not `load()` any databases yet.
-    pub fn open(flags: Flags) -> Result<Magic, FileMagicError> {
-        let cookie;
-        unsafe {
-            cookie = api::magic_open((flags | Flags::ERROR).bits());
+    /// With `Flags::RAW`, libmagic copies bytes from the data into the description
+    /// as they are, so it isn't always valid UTF-8.
+    pub fn file_bytes<P: AsRef<Path>>(&self, filename: P) -> Result<Vec<u8>, FileMagicError> {
+        let path = filename.as_ref();
+        let f = path_cstring(path)?;
+        let str = self.traced(|| unsafe { api::magic_file(self.magic, f.as_ptr()) });
+        unsafe { self.output(str, Some(path)) }
+    }
+
+    /// Returns a textual description of the contents of the `buffer`
+    ///
+    /// Bytes of the description that are not valid UTF-8 are replaced with
+    /// `U+FFFD`; use `buffer_bytes()` to get them unchanged.
+    pub fn buffer(&self, buffer: &[u8]) -> Result<String, FileMagicError> {
+        self.buffer_bytes(buffer).map(lossy)
+    }
+
+    /// Returns the raw description of the contents of the `buffer`
+    pub fn buffer_bytes(&self, buffer: &[u8]) -> Result<Vec<u8>, FileMagicError> {
+        let buffer_len = buffer.len() as size_t;
+        let pbuffer = buffer.as_ptr();
+        let str = self.traced(|| unsafe { api::magic_buffer(self.magic, pbuffer, buffer_len) });
+        unsafe { self.output(str, None) }
+    }
+
+    /// Returns a textual description of the contents read from the open file descriptor `fd`
+    ///
+    /// libmagic reads from the current file offset onward, so seek to the start first to
+    /// look at a whole file. For seekable files the offset is restored afterwards, while
+    /// pipes and sockets lose the bytes that were read. `fd` is not closed.
+    #[cfg(unix)]
+    pub fn descriptor(&self, fd: &impl AsFd) -> Result<String, FileMagicError> {
+        self.descriptor_bytes(fd).map(lossy)
+    }
+
+    /// Returns the raw description of the contents read from the open file descriptor `fd`
+    ///
+    /// See `descriptor()` for how the file offset is used.
+    #[cfg(unix)]
+    pub fn descriptor_bytes(&self, fd: &impl AsFd) -> Result<Vec<u8>, FileMagicError> {
+        let fd = fd.as_fd().as_raw_fd();
+        let str = self.traced(|| unsafe { api::magic_descriptor(self.magic, fd) });
+        unsafe { self.output(str, None) }
+    }
+
+    /// Returns a textual description of the contents of the opened `file`
+    ///
+    /// See `descriptor()` for how the file offset is used.
+    #[cfg(unix)]
+    pub fn descriptor_file(&self, file: &File) -> Result<String, FileMagicError> {
+        self.descriptor(file)
+    }
+
+    /// Returns a textual description of the data read from `reader`
+    ///
+    /// Only as many bytes as libmagic would look at in a file are read. Rules that test
+    /// the end of a file can't match this way; use `seekable_reader()` for those.
+    pub fn reader<R: Read>(&self, reader: R) -> Result<String, FileMagicError> {
+        let mut buffer = Vec::new();
+        reader
+            .take(self.bytes_max() as u64)
+            .read_to_end(&mut buffer)
+            .map_err(FileMagicError::io)?;
+        self.buffer(&buffer)
+    }
+
+    /// Returns a textual description of the data read from `reader`, from its current
+    /// position to its end
+    ///
+    /// Like libmagic does for files, this reads a window from the start and another one
+    /// from the end of the data, so that rules testing the end of a file still match.
+    /// libmagic can only evaluate those rules on a file descriptor, which is backed by an
+    /// anonymous in-memory file on Linux and an unlinked temporary file on other Unix
+    /// systems; elsewhere only the start of the data is used.
+    /// The position of `reader` is restored afterwards.
+    pub fn seekable_reader<R: Read + Seek>(&self, mut reader: R) -> Result<String, FileMagicError> {
+        let start = reader.stream_position().map_err(FileMagicError::io)?;
+        let end = reader.seek(SeekFrom::End(0)).map_err(FileMagicError::io)?;
+        reader
+            .seek(SeekFrom::Start(start))
+            .map_err(FileMagicError::io)?;
+
+        let snapshot = Snapshot::take(
+            &mut reader,
+            start,
+            end.saturating_sub(start),
+            self.bytes_max() as u64,
+        );
+        reader
+            .seek(SeekFrom::Start(start))
+            .map_err(FileMagicError::io)?;
+        snapshot.map_err(FileMagicError::io)?.detect(self)
+    }
+
+    /// Returns what every output mode of libmagic reports for the contents of the `filename`
+    ///
+    /// The contents are only read once and kept in memory while the output modes run,
+    /// except for setuid, setgid and sticky files, whose modes libmagic describes too.
+    /// Flags other than the output modes are honoured, and the flags of the cookie are
+    /// the same afterwards. `extensions` and `apple` are left empty for files libmagic
+    /// can't report them for, such as directories.
+    pub fn identify<P: AsRef<Path>>(&self, filename: P) -> Result<FileType, FileMagicError> {
+        let filename = filename.as_ref();
+        match self.snapshot_file(filename) {
+            Some(snapshot) => self.identify_with(|cookie| snapshot.detect(cookie)),
+            None => self.identify_with(|cookie| cookie.file(filename)),
         }
-        if cookie.is_null() {
-            Err(self::FileMagicError {
-                desc: "errno".to_string(),
-            })
+    }
+
+    /// Returns what every output mode of libmagic reports for the contents of the `buffer`
+    ///
+    /// The flags of the cookie are the same afterwards.
+    pub fn identify_buffer(&self, buffer: &[u8]) -> Result<FileType, FileMagicError> {
+        self.identify_with(|cookie| cookie.buffer(buffer))
+    }
+
+    /// Returns every match libmagic finds in the contents of the `filename`, in order
+    ///
+    /// This is the textual description with `Flags::CONTINUE`, split into its matches.
+    /// Output mode flags like `Flags::MIME_TYPE` are ignored, and the flags of the
+    /// cookie are the same afterwards.
+    pub fn file_all<P: AsRef<Path>>(&self, filename: P) -> Result<Vec<Match>, FileMagicError> {
+        self.matches_with(|cookie| cookie.file_bytes(filename.as_ref()))
+    }
+
+    /// Returns every match libmagic finds in the contents of the `buffer`, in order
+    ///
+    /// See `file_all()` for how the flags are used.
+    pub fn buffer_all(&self, buffer: &[u8]) -> Result<Vec<Match>, FileMagicError> {
+        self.matches_with(|cookie| cookie.buffer_bytes(buffer))
+    }
+
+    fn matches_with<F>(&self, detect: F) -> Result<Vec<Match>, FileMagicError>
+    where
+        F: FnOnce(&Self) -> Result<Vec<u8>, FileMagicError>,
+    {
+        let flags = self.flags();
+        // unescaped, so that the separators can't be confused with the data
+        let mode = (flags - Flags::MAGIC_NODESC) | Flags::CONTINUE | Flags::RAW;
+        let output = self.with_mode(mode, detect)?;
+        Ok(matches::parse_matches(&output, flags.contains(Flags::RAW)))
+    }
+
+    /// Returns the usual extensions for the contents of the `filename`, preferred one first
+    ///
+    /// Returns `None` if libmagic doesn't know any. Fails if the linked libmagic doesn't
+    /// support `Flags::EXTENSION`. The flags of the cookie are the same afterwards.
+    pub fn extensions<P: AsRef<Path>>(
+        &self,
+        filename: P,
+    ) -> Result<Option<Vec<String>>, FileMagicError> {
+        self.extensions_with(|cookie| cookie.file_bytes(filename.as_ref()))
+    }
+
+    /// Returns the usual extensions for the contents of the `buffer`, preferred one first
+    ///
+    /// See `extensions()` for details.
+    pub fn buffer_extensions(&self, buffer: &[u8]) -> Result<Option<Vec<String>>, FileMagicError> {
+        self.extensions_with(|cookie| cookie.buffer_bytes(buffer))
+    }
+
+    fn extensions_with<F>(&self, detect: F) -> Result<Option<Vec<String>>, FileMagicError>
+    where
+        F: FnOnce(&Self) -> Result<Vec<u8>, FileMagicError>,
+    {
+        check_flags(Flags::EXTENSION)?;
+        let mode = (self.flags() - Flags::MAGIC_NODESC - Flags::CONTINUE) | Flags::EXTENSION;
+        let output = lossy(self.with_mode(mode, detect)?);
+        let extensions = file_type::parse_extensions(&output);
+        Ok(if extensions.is_empty() {
+            None
         } else {
-            Ok(Magic { magic: cookie })
-        }
+            Some(extensions)
+        })
     }
 
-    /// Loads the given database `filenames` for further queries
-    /// Adds '.mgc' to the database files as appropriate.
-    pub fn load<P: AsRef<Path>>(&self, magic_databases: &[P]) -> Result<(), FileMagicError> {
-        let cookie = self.magic;
-        let db_filenames = db_filenames(magic_databases);
-        let ret;
+    /// Returns the MIME type and encoding of the contents of the `filename`
+    ///
+    /// The flags of the cookie are the same afterwards.
+    pub fn mime<P: AsRef<Path>>(&self, filename: P) -> Result<Mime, FileMagicError> {
+        self.mime_with(|cookie| cookie.file_bytes(filename.as_ref()))
+    }
 
-        unsafe {
-            ret = api::magic_load(cookie, db_filenames);
-        }
-        if 0 == ret {
-            Ok(())
+    /// Returns the MIME type and encoding of the contents of the `buffer`
+    ///
+    /// The flags of the cookie are the same afterwards.
+    pub fn mime_buffer(&self, buffer: &[u8]) -> Result<Mime, FileMagicError> {
+        self.mime_with(|cookie| cookie.buffer_bytes(buffer))
+    }
+
+    fn mime_with<F>(&self, detect: F) -> Result<Mime, FileMagicError>
+    where
+        F: FnOnce(&Self) -> Result<Vec<u8>, FileMagicError>,
+    {
+        let mode = (self.flags() - Flags::MAGIC_NODESC - Flags::CONTINUE) | Flags::MIME;
+        let output = lossy(self.with_mode(mode, detect)?);
+        crate::mime::parse_mime(&output).ok_or_else(|| {
+            FileMagicError::new(
+                ErrorKind::Detection,
+                format!("libmagic returned `{}', which is not a MIME type", output),
+            )
+        })
+    }
+
+    /// Returns the classic Mac OS creator and type codes for the contents of the `filename`
+    ///
+    /// Returns `None` if libmagic doesn't know them. The flags of the cookie are the
+    /// same afterwards.
+    pub fn apple_type<P: AsRef<Path>>(
+        &self,
+        filename: P,
+    ) -> Result<Option<AppleType>, FileMagicError> {
+        self.apple_type_with(|cookie| cookie.file_bytes(filename.as_ref()))
+    }
+
+    /// Returns the classic Mac OS creator and type codes for the contents of the `buffer`
+    ///
+    /// See `apple_type()` for details.
+    pub fn buffer_apple_type(&self, buffer: &[u8]) -> Result<Option<AppleType>, FileMagicError> {
+        self.apple_type_with(|cookie| cookie.buffer_bytes(buffer))
+    }
+
+    fn apple_type_with<F>(&self, detect: F) -> Result<Option<AppleType>, FileMagicError>
+    where
+        F: FnOnce(&Self) -> Result<Vec<u8>, FileMagicError>,
+    {
+        // unescaped, so that codes outside of ASCII keep their four bytes
+        let mode =
+            (self.flags() - Flags::MAGIC_NODESC - Flags::CONTINUE) | Flags::APPLE | Flags::RAW;
+        let output = self.with_mode(mode, detect)?;
+        Ok(apple::parse_apple(&output))
+    }
+
+    /// Copies the contents of the regular file `filename`
+    ///
+    /// Returns `None` for anything else, which libmagic describes without reading it,
+    /// for files with mode bits libmagic describes, which the copy wouldn't have, and
+    /// for files that can't be read, so that libmagic reports the error.
+    fn snapshot_file(&self, filename: &Path) -> Option<Snapshot> {
+        let metadata = if self.flags().contains(Flags::SYMLINK) {
+            std::fs::metadata(filename)
         } else {
-            Err(self.magic_failure())
+            std::fs::symlink_metadata(filename)
         }
+        .ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        // setuid, setgid and sticky
+        #[cfg(unix)]
+        if std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7000 != 0 {
+            return None;
+        }
+        let mut file = std::fs::File::open(filename).ok()?;
+        let len = file.metadata().ok()?.len();
+        Snapshot::take(&mut file, 0, len, self.bytes_max() as u64).ok()
+    }
+
+    fn identify_with<F>(&self, detect: F) -> Result<FileType, FileMagicError>
+    where
+        F: Fn(&Self) -> Result<String, FileMagicError>,
+    {
+        let flags = self.flags();
+        let description = flags - Flags::MAGIC_NODESC;
+        // the other output modes have a single answer
+        let single = description - Flags::CONTINUE;
+
+        let run = |mode: Flags| {
+            unsafe { api::magic_setflags(self.magic, mode.bits()) };
+            detect(self)
+        };
+        let file_type = (|| {
+            Ok(FileType {
+                description: run(description)?,
+                mime_type: run(single | Flags::MIME_TYPE)?,
+                mime_encoding: run(single | Flags::MIME_ENCODING)?,
+                extensions: run(single | Flags::EXTENSION)
+                    .map(|output| file_type::parse_extensions(&output))
+                    .unwrap_or_default(),
+                apple: run(single | Flags::APPLE | Flags::RAW)
+                    .ok()
+                    .and_then(|output| apple::parse_apple(output.as_bytes())),
+            })
+        })();
+
+        unsafe { api::magic_setflags(self.magic, flags.bits()) };
+        file_type
     }
 }
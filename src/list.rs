@@ -0,0 +1,152 @@
+//! The entries of magic databases as listed by `magic_list()`
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A top-level test of a magic database, along with what it reports when it matches
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct MagicEntry {
+    /// How early libmagic tries this test, higher first
+    pub strength: usize,
+    /// The line of the test in its source file
+    pub line: u32,
+    /// Whether this is a text test, which libmagic tries after the binary ones
+    pub text: bool,
+    /// The offset of the test, like `0` or `(4.l+2)`
+    ///
+    /// `None` if the source of a compiled database can't be read.
+    pub offset: Option<String>,
+    /// The type of the test, like `string` or `belong&0xff`
+    ///
+    /// `None` if the source of a compiled database can't be read.
+    pub test_type: Option<String>,
+    /// The value tested for, like `\x89PNG`
+    ///
+    /// `None` if the source of a compiled database can't be read.
+    pub test_value: Option<String>,
+    /// The description of the test or its first continuation that has one
+    pub description: String,
+    /// The MIME type of the test or its first continuation that has one, if any
+    pub mime_type: Option<String>,
+    /// The usual file extensions of the test or its first continuation that has some
+    ///
+    /// Empty if there are none, or if the source of a compiled database can't be read.
+    pub extensions: Vec<String>,
+}
+
+/// Returns the files `magic_list()` should look at one by one, so that the line
+/// numbers it reports point into a known file
+pub(crate) fn database_files<P: AsRef<Path>>(filenames: &[P]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for filename in filenames {
+        let path = filename.as_ref();
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => {
+                files.push(path.to_path_buf());
+                continue;
+            }
+        };
+        // libmagic reads the files of a directory sorted by name
+        let mut dir_files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        dir_files.sort();
+        files.extend(dir_files);
+    }
+    files
+}
+
+/// Parses the lines `magic_list()` prints, like `Strength = 150@1: PNG image data [image/png]`
+pub(crate) fn parse_list(output: &str) -> Vec<MagicEntry> {
+    let mut entries = Vec::new();
+    let mut text = false;
+    for line in output.lines() {
+        match line {
+            "Binary patterns:" => text = false,
+            "Text patterns:" => text = true,
+            _ => entries.extend(parse_entry(line, text)),
+        }
+    }
+    entries
+}
+
+fn parse_entry(line: &str, text: bool) -> Option<MagicEntry> {
+    let rest = line.strip_prefix("Strength = ")?;
+    let (strength, rest) = rest.split_once('@')?;
+    let (lineno, rest) = rest.split_once(": ")?;
+    let (description, mime_type) = rest.strip_suffix(']')?.rsplit_once(" [")?;
+    Some(MagicEntry {
+        strength: strength.trim().parse().ok()?,
+        line: lineno.parse().ok()?,
+        text,
+        description: description.to_string(),
+        mime_type: Some(mime_type.to_string()).filter(|mime| !mime.is_empty()),
+        ..MagicEntry::default()
+    })
+}
+
+/// The first bytes of a compiled database, in either byte order
+const COMPILED_MAGIC: [[u8; 4]; 2] = [[0x1c, 0x04, 0x1e, 0xf1], [0xf1, 0x1e, 0x04, 0x1c]];
+
+/// Returns whether `data` is a compiled database rather than its source
+pub(crate) fn is_compiled(data: &[u8]) -> bool {
+    COMPILED_MAGIC.iter().any(|magic| data.starts_with(magic))
+}
+
+/// Fills in what only the `source` of the listed database tells about the `entries`
+pub(crate) fn annotate(entries: &mut [MagicEntry], source: &str) {
+    let lines: Vec<&str> = source.lines().collect();
+    for entry in entries {
+        let at = match (entry.line as usize).checked_sub(1) {
+            Some(at) if at < lines.len() => at,
+            _ => continue,
+        };
+        let fields = split_fields(lines[at]);
+        if fields.len() < 3 {
+            continue;
+        }
+        entry.offset = Some(fields[0].to_string());
+        entry.test_type = Some(fields[1].to_string());
+        entry.test_value = Some(fields[2].to_string());
+
+        // the extensions belong to the test or one of its continuations
+        let block = lines[at + 1..]
+            .iter()
+            .map(|line| line.trim_start())
+            .take_while(|line| line.is_empty() || line.starts_with(['>', '!', '#']));
+        for line in block {
+            if let Some(extensions) = line.strip_prefix("!:ext") {
+                entry.extensions = extensions
+                    .trim()
+                    .split('/')
+                    .filter(|extension| !extension.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                break;
+            }
+        }
+    }
+}
+
+/// Splits the offset, type and value of a magic line, where `\` escapes whitespace
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim_start();
+    while fields.len() < 3 && !rest.is_empty() {
+        let mut escaped = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                let split = !escaped && c.is_whitespace();
+                escaped = !escaped && c == '\\';
+                split
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    fields
+}
@@ -1,6 +1,7 @@
 ///
 /// Returns the Mime type of a file
 ///
+/// Without explicit databases, the default one from `Magic::load_default()` is used.
 ///
 /// ### Usage Example
 ///
@@ -13,19 +14,15 @@
 #[macro_export]
 macro_rules! magic {
     () => {
-        $crate::Magic::open(Default::default())
-            .and_then(|magic| magic.load::<&str>(&[]).and_then(|_| Ok(magic)))
+        $crate::Magic::open(Default::default()).and_then(|magic| magic.load_default())
     };
     ($flags:expr) => {
-        $crate::Magic::open($flags)
-            .and_then(|magic| magic.load::<&str>(&[]).and_then(|_| Ok(magic)))
+        $crate::Magic::open($flags).and_then(|magic| magic.load_default())
     };
     (,$magic_databases:expr) => {
-        $crate::Magic::open(Default::default())
-            .and_then(|magic| magic.load($magic_databases).and_then(|_| Ok(magic)))
+        $crate::Magic::open(Default::default()).and_then(|magic| magic.load($magic_databases))
     };
     ($flags:expr, $magic_databases:expr) => {
-        $crate::Magic::open($flags)
-            .and_then(|magic| magic.load($magic_databases).and_then(|_| Ok(magic)))
+        $crate::Magic::open($flags).and_then(|magic| magic.load($magic_databases))
     };
 }
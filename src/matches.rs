@@ -0,0 +1,70 @@
+//! The separate matches libmagic reports with `Flags::CONTINUE`
+use std::fmt::{self, Display};
+
+/// One of the matches libmagic found for some data
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Match {
+    /// The textual description of the match
+    pub description: String,
+}
+
+impl Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+/// libmagic puts this between matches, before escaping unprintable characters
+const SEPARATOR: &[u8] = b"\n- ";
+
+/// Splits the output of `Flags::CONTINUE | Flags::RAW` into its matches
+///
+/// Unless `raw` is set, unprintable bytes of each match are escaped the way libmagic
+/// does it in the C locale.
+pub(crate) fn parse_matches(output: &[u8], raw: bool) -> Vec<Match> {
+    let mut parts = Vec::new();
+    let mut rest = output;
+    while let Some(at) = find(rest, SEPARATOR) {
+        parts.push(&rest[..at]);
+        rest = &rest[at + SEPARATOR.len()..];
+    }
+    parts.push(rest);
+
+    let mut matches: Vec<Match> = parts
+        .into_iter()
+        // the text tests expect to be appended to a previous description
+        .map(|part| part.strip_prefix(b", ").unwrap_or(part))
+        .filter(|part| !part.is_empty())
+        .map(|part| Match {
+            description: if raw {
+                String::from_utf8_lossy(part).into_owned()
+            } else {
+                escape(part)
+            },
+        })
+        .collect();
+    // the fallback when the last tests didn't match, even though earlier ones did
+    if matches.len() > 1 && matches.last().is_some_and(|m| m.description == "data") {
+        matches.pop();
+    }
+    matches
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Escapes unprintable bytes as `\ooo`, like libmagic without `Flags::RAW`
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        if (0x20..0x7f).contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:03o}", byte));
+        }
+    }
+    escaped
+}
@@ -0,0 +1,135 @@
+//! MIME types as reported with `Flags::MIME`
+use std::fmt::{self, Display};
+
+/// A MIME type with its parameters, like `text/plain; charset=us-ascii`
+///
+/// The type, subtype, suffix and parameter names are lowercase, as they are case
+/// insensitive.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Hash)]
+pub struct Mime {
+    /// The top-level type, like `text`
+    pub top_level: String,
+    /// The subtype without its suffix, like `svg` in `image/svg+xml`
+    pub subtype: String,
+    /// The structured syntax suffix without its `+`, like `xml` in `image/svg+xml`
+    pub suffix: Option<String>,
+    /// The parameters in the order libmagic gave them, like `("charset", "us-ascii")`
+    pub params: Vec<(String, String)>,
+}
+
+impl Mime {
+    /// Returns the type without its parameters, like `image/svg+xml`
+    pub fn essence(&self) -> String {
+        match &self.suffix {
+            Some(suffix) => format!("{}/{}+{}", self.top_level, self.subtype, suffix),
+            None => format!("{}/{}", self.top_level, self.subtype),
+        }
+    }
+
+    /// Returns the value of the parameter `name`, if any
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the `charset` parameter, if any
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+}
+
+impl Display for Mime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.essence())?;
+        for (name, value) in &self.params {
+            let token = !value.is_empty()
+                && value
+                    .bytes()
+                    .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?=".contains(&b));
+            if token {
+                write!(f, "; {}={}", name, value)?;
+            } else {
+                let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {}=\"{}\"", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the output of `Flags::MIME` or `Flags::MIME_TYPE`
+///
+/// Returns `None` if it is not a MIME type.
+pub(crate) fn parse_mime(output: &str) -> Option<Mime> {
+    let (essence, mut rest) = match output.find(';') {
+        Some(at) => (&output[..at], &output[at + 1..]),
+        None => (output, ""),
+    };
+    let (top_level, subtype) = essence.trim().split_once('/')?;
+    if top_level.is_empty() || subtype.is_empty() {
+        return None;
+    }
+    let (subtype, suffix) = match subtype.rsplit_once('+') {
+        Some((subtype, suffix)) if !subtype.is_empty() => (subtype, Some(suffix)),
+        _ => (subtype, None),
+    };
+
+    let mut params = Vec::new();
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            break;
+        }
+        let (name, after) = rest.split_once('=')?;
+        let value;
+        if let Some(quoted) = after.strip_prefix('"') {
+            let mut unquoted = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => unquoted.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = Some(i + 1);
+                        break;
+                    }
+                    c => unquoted.push(c),
+                }
+            }
+            value = unquoted;
+            rest = &quoted[end?..];
+        } else {
+            let end = after.find(';').unwrap_or(after.len());
+            value = after[..end].trim_end().to_string();
+            rest = &after[end..];
+        }
+        params.push((name.trim().to_ascii_lowercase(), value));
+    }
+
+    Some(Mime {
+        top_level: top_level.to_ascii_lowercase(),
+        subtype: subtype.to_ascii_lowercase(),
+        suffix: suffix.map(str::to_ascii_lowercase),
+        params,
+    })
+}
+
+#[cfg(feature = "mime")]
+impl TryFrom<&Mime> for ::mime::Mime {
+    type Error = ::mime::FromStrError;
+
+    fn try_from(mime: &Mime) -> Result<::mime::Mime, ::mime::FromStrError> {
+        mime.to_string().parse()
+    }
+}
+
+#[cfg(feature = "mime")]
+impl TryFrom<Mime> for ::mime::Mime {
+    type Error = ::mime::FromStrError;
+
+    fn try_from(mime: Mime) -> Result<::mime::Mime, ::mime::FromStrError> {
+        ::mime::Mime::try_from(&mime)
+    }
+}
@@ -0,0 +1,89 @@
+/// Parameters which limit how much work `libmagic` does
+use libc::c_int;
+
+/// Tunable limits of `libmagic`, used with `Magic::set_param` and `Magic::get_param`
+///
+/// NOTE: The descriptions are taken from `man libmagic 3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Param {
+    /// How many levels of recursion will be followed for indirect magic entries
+    IndirMax,
+    /// The maximum number of calls for name/use
+    NameMax,
+    /// How many ELF program sections will be processed
+    ElfPhnumMax,
+    /// How many ELF sections will be processed
+    ElfShnumMax,
+    /// How many ELF notes will be processed
+    ElfNotesMax,
+    /// The length limit for regex searches
+    RegexMax,
+    /// The maximum number of bytes to read from a file
+    BytesMax,
+    /// The maximum number of bytes to scan for encoding detection
+    EncodingMax,
+    /// The maximum ELF section size to process
+    #[cfg(feature = "v5-45")]
+    ElfShsizeMax,
+}
+
+impl Param {
+    /// Every parameter known to this crate
+    pub const ALL: &'static [Param] = &[
+        Param::IndirMax,
+        Param::NameMax,
+        Param::ElfPhnumMax,
+        Param::ElfShnumMax,
+        Param::ElfNotesMax,
+        Param::RegexMax,
+        Param::BytesMax,
+        Param::EncodingMax,
+        #[cfg(feature = "v5-45")]
+        Param::ElfShsizeMax,
+    ];
+
+    /// The `MAGIC_PARAM_*` value `libmagic` uses for the parameter
+    pub(crate) fn raw(self) -> c_int {
+        match self {
+            Param::IndirMax => 0,
+            Param::NameMax => 1,
+            Param::ElfPhnumMax => 2,
+            Param::ElfShnumMax => 3,
+            Param::ElfNotesMax => 4,
+            Param::RegexMax => 5,
+            Param::BytesMax => 6,
+            Param::EncodingMax => 7,
+            #[cfg(feature = "v5-45")]
+            Param::ElfShsizeMax => 8,
+        }
+    }
+
+    /// The first `libmagic` version supporting the parameter, as returned by `magic_version()`
+    pub(crate) fn min_version(self) -> c_int {
+        match self {
+            Param::IndirMax | Param::NameMax | Param::ElfPhnumMax | Param::ElfShnumMax => 521,
+            Param::ElfNotesMax => 522,
+            Param::RegexMax => 525,
+            Param::BytesMax => 527,
+            Param::EncodingMax => 540,
+            #[cfg(feature = "v5-45")]
+            Param::ElfShsizeMax => 545,
+        }
+    }
+
+    /// The name of the parameter without its `MAGIC_PARAM_` prefix
+    pub fn name(self) -> &'static str {
+        match self {
+            Param::IndirMax => "INDIR_MAX",
+            Param::NameMax => "NAME_MAX",
+            Param::ElfPhnumMax => "ELF_PHNUM_MAX",
+            Param::ElfShnumMax => "ELF_SHNUM_MAX",
+            Param::ElfNotesMax => "ELF_NOTES_MAX",
+            Param::RegexMax => "REGEX_MAX",
+            Param::BytesMax => "BYTES_MAX",
+            Param::EncodingMax => "ENCODING_MAX",
+            #[cfg(feature = "v5-45")]
+            Param::ElfShsizeMax => "ELF_SHSIZE_MAX",
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! A pool of cookies shared between threads
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, OnceLock},
+};
+
+use crate::{FileMagicError, Flags, Magic, Param};
+
+/// A fixed-size pool of cookies that share the same `Flags` and magic databases
+///
+/// Cookies are only created when needed, up to the size of the pool. Each one is
+/// checked out exclusively through a `PooledMagic` guard. When the guard is dropped, the
+/// cookie gets back the pool's flags and the parameters it was created with, and loses
+/// its debug handler; a cookie that can't be reset is closed instead.
+pub struct MagicPool {
+    flags: Flags,
+    databases: Vec<PathBuf>,
+    size: usize,
+    // the parameters of the first cookie created, which all cookies start with
+    params: OnceLock<Vec<(Param, usize)>>,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<Magic<'static>>,
+    created: usize,
+}
+
+/// A cookie checked out of a `MagicPool`, which goes back to the pool when dropped
+pub struct PooledMagic<'pool> {
+    pool: &'pool MagicPool,
+    cookie: Option<Magic<'static>>,
+}
+
+impl Deref for PooledMagic<'_> {
+    type Target = Magic<'static>;
+
+    fn deref(&self) -> &Magic<'static> {
+        self.cookie.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledMagic<'_> {
+    fn drop(&mut self) {
+        if let Some(cookie) = self.cookie.take() {
+            self.pool.put_back(cookie);
+        }
+    }
+}
+
+impl MagicPool {
+    /// Creates a pool of at most `size` cookies with the given `flags` and `magic_databases`
+    ///
+    /// Without databases, the default one from `Magic::load_default()` is used. No cookie
+    /// is created yet, so errors loading the databases surface in `get()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new<P: AsRef<Path>>(flags: Flags, magic_databases: &[P], size: usize) -> MagicPool {
+        assert!(size > 0, "MagicPool needs room for at least one cookie");
+        MagicPool {
+            flags,
+            databases: magic_databases
+                .iter()
+                .map(|db| db.as_ref().to_path_buf())
+                .collect(),
+            size,
+            params: OnceLock::new(),
+            state: Mutex::new(PoolState {
+                idle: Vec::with_capacity(size),
+                created: 0,
+            }),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Returns the maximum number of cookies in the pool
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Checks out a cookie, waiting for one to be returned if all of them are in use
+    pub fn get(&self) -> Result<PooledMagic<'_>, FileMagicError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(cookie) = state.idle.pop() {
+                return Ok(self.checkout(cookie));
+            }
+            if state.created < self.size {
+                state.created += 1;
+                drop(state);
+                return self.create().map(|cookie| self.checkout(cookie));
+            }
+            state = self.returned.wait(state).unwrap();
+        }
+    }
+
+    /// Checks out a cookie if one is available without waiting
+    pub fn try_get(&self) -> Option<Result<PooledMagic<'_>, FileMagicError>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(cookie) = state.idle.pop() {
+            return Some(Ok(self.checkout(cookie)));
+        }
+        if state.created < self.size {
+            state.created += 1;
+            drop(state);
+            return Some(self.create().map(|cookie| self.checkout(cookie)));
+        }
+        None
+    }
+
+    fn checkout(&self, cookie: Magic<'static>) -> PooledMagic<'_> {
+        PooledMagic {
+            pool: self,
+            cookie: Some(cookie),
+        }
+    }
+
+    /// Creates a new cookie for a slot already counted in `created`
+    fn create(&self) -> Result<Magic<'static>, FileMagicError> {
+        let cookie = Magic::open(self.flags).and_then(|cookie| {
+            if self.databases.is_empty() {
+                cookie.load_default()
+            } else {
+                cookie.load(&self.databases)
+            }
+        });
+        match &cookie {
+            Ok(cookie) => {
+                self.params.get_or_init(|| {
+                    Param::ALL
+                        .iter()
+                        .filter_map(|&param| Some((param, cookie.get_param(param).ok()?)))
+                        .collect()
+                });
+            }
+            Err(_) => self.forget(),
+        }
+        cookie
+    }
+
+    fn put_back(&self, cookie: Magic<'static>) {
+        if self.reset(&cookie).is_err() {
+            drop(cookie);
+            self.forget();
+            return;
+        }
+        self.state.lock().unwrap().idle.push(cookie);
+        self.returned.notify_one();
+    }
+
+    /// Undoes what a borrower may have changed through `PooledMagic`
+    fn reset(&self, cookie: &Magic<'static>) -> Result<(), FileMagicError> {
+        cookie.set_debug_handler(None);
+        // `open()` always adds `ERROR`, so keep it when resetting
+        cookie.set_flags(self.flags | Flags::ERROR)?;
+        for &(param, value) in self.params.get().into_iter().flatten() {
+            cookie.set_param(param, value)?;
+        }
+        Ok(())
+    }
+
+    /// Frees the slot of a cookie that is gone, so a waiting `get()` can create another
+    fn forget(&self) {
+        self.state.lock().unwrap().created -= 1;
+        self.returned.notify_one();
+    }
+}
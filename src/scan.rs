@@ -0,0 +1,461 @@
+//! Identifying every file below a directory with several threads
+use std::{
+    collections::HashSet,
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+use crate::{ErrorKind, FileMagicError, FileType, Flags, Magic, MagicBuilder};
+
+/// What a `Scan` yields for each file: its path and what libmagic reports about it
+pub type ScanResult = (PathBuf, Result<FileType, FileMagicError>);
+
+/// Walks directory trees and identifies every file in them with several cookies at once
+///
+/// One thread walks the tree, while each worker thread identifies files with its own
+/// cookie, so results come in no particular order. Directories that can't be read are
+/// reported as errors too.
+///
+/// ```no_run
+/// use filemagic::scan::Scanner;
+///
+/// let scanner = Scanner::new().exclude(".git/").max_size(1 << 30);
+/// for (path, file_type) in scanner.scan("/data").expect("error") {
+///     match file_type {
+///         Ok(file_type) => println!("{}: {}", path.display(), file_type.mime_type),
+///         Err(err) => eprintln!("{}: {}", path.display(), err),
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scanner {
+    builder: MagicBuilder<'static>,
+    flags: Flags,
+    workers: usize,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+    same_device: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    excludes: Vec<Exclude>,
+}
+
+impl Default for Scanner {
+    fn default() -> Scanner {
+        Scanner {
+            builder: MagicBuilder::new(),
+            flags: Flags::NONE,
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            follow_symlinks: false,
+            max_depth: None,
+            same_device: false,
+            min_size: None,
+            max_size: None,
+            excludes: Vec::new(),
+        }
+    }
+}
+
+impl Scanner {
+    /// Creates a scanner with the default database, one worker per CPU, and no filters
+    pub fn new() -> Scanner {
+        Scanner::default()
+    }
+
+    /// Sets the flags of the cookies, replacing any set before
+    pub fn flags(mut self, flags: Flags) -> Scanner {
+        self.flags = flags;
+        self
+    }
+
+    /// Adds the database file `path`, as `Magic::load()` takes it
+    pub fn database<P: AsRef<Path>>(mut self, path: P) -> Scanner {
+        self.builder = self.builder.database(path);
+        self
+    }
+
+    /// Sets how many files are identified at once
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is zero.
+    pub fn workers(mut self, workers: usize) -> Scanner {
+        assert!(workers > 0, "Scanner needs at least one worker");
+        self.workers = workers;
+        self
+    }
+
+    /// Sets whether to follow symlinks, both when walking and when identifying
+    ///
+    /// Off by default, so that symlinks are reported as such. Directories already
+    /// visited through another link are skipped.
+    pub fn follow_symlinks(mut self, follow: bool) -> Scanner {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Sets how deep to walk, where the entries of the root directory are at depth 1
+    pub fn max_depth(mut self, depth: usize) -> Scanner {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Sets whether to stay on the file system of the root directory
+    ///
+    /// Only has an effect on Unix.
+    pub fn same_device(mut self, same: bool) -> Scanner {
+        self.same_device = same;
+        self
+    }
+
+    /// Skips regular files smaller than `size` bytes
+    pub fn min_size(mut self, size: u64) -> Scanner {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Skips regular files larger than `size` bytes
+    pub fn max_size(mut self, size: u64) -> Scanner {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Skips the paths matching `pattern`, which uses the syntax of `.gitignore` lines
+    ///
+    /// Patterns are matched against the path relative to the root directory. As in
+    /// `.gitignore`, later patterns take precedence, `!` re-includes what an earlier
+    /// pattern excluded, and a trailing `/` only matches directories. Nothing below an
+    /// excluded directory is looked at.
+    pub fn exclude(mut self, pattern: &str) -> Scanner {
+        self.excludes.extend(Exclude::parse(pattern));
+        self
+    }
+
+    /// Starts identifying everything below `root`, or `root` itself if it is a file
+    ///
+    /// Fails if the cookies can't be created, for instance if a database doesn't load.
+    pub fn scan<P: AsRef<Path>>(&self, root: P) -> Result<Scan, FileMagicError> {
+        let flags = if self.follow_symlinks {
+            self.flags | Flags::SYMLINK
+        } else {
+            self.flags
+        };
+        let builder = self.builder.clone().flags(flags);
+        let cookies = (0..self.workers)
+            .map(|_| builder.build())
+            .collect::<Result<Vec<_>, FileMagicError>>()?;
+
+        // both bounded, so that a slow consumer holds back the workers and the walk
+        let (results, receiver) = mpsc::sync_channel(self.workers * 4);
+        let (paths, queue) = mpsc::sync_channel(self.workers * 4);
+        let queue = Arc::new(Mutex::new(queue));
+        for cookie in cookies {
+            let queue = queue.clone();
+            let results = results.clone();
+            thread::spawn(move || identify(cookie, &queue, &results));
+        }
+
+        let walker = Walker {
+            root: root.as_ref().to_path_buf(),
+            options: self.clone(),
+            paths,
+            results,
+            visited: HashSet::new(),
+        };
+        thread::spawn(move || walker.walk());
+
+        Ok(Scan { receiver })
+    }
+}
+
+/// The results of `Scanner::scan()`, as they come in
+///
+/// Dropping this stops the scan.
+pub struct Scan {
+    receiver: Receiver<ScanResult>,
+}
+
+impl Iterator for Scan {
+    type Item = ScanResult;
+
+    fn next(&mut self) -> Option<ScanResult> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Identifies the paths from `queue` until it is empty or the results are dropped
+fn identify(
+    cookie: Magic<'static>,
+    queue: &Mutex<Receiver<PathBuf>>,
+    results: &SyncSender<ScanResult>,
+) {
+    loop {
+        let path = match queue.lock().unwrap().recv() {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        let file_type = cookie.identify(&path);
+        if results.send((path, file_type)).is_err() {
+            return;
+        }
+    }
+}
+
+struct Walker {
+    root: PathBuf,
+    options: Scanner,
+    paths: SyncSender<PathBuf>,
+    results: SyncSender<ScanResult>,
+    // directories entered while following symlinks, to not go round in circles
+    visited: HashSet<PathBuf>,
+}
+
+impl Walker {
+    fn walk(mut self) {
+        let root = self.root.clone();
+        match self.metadata(&root) {
+            Ok(metadata) if metadata.is_dir() => {
+                let _ = self.walk_dir(&root, 1, device(&metadata));
+            }
+            Ok(metadata) => {
+                if self.accepts_size(&metadata) {
+                    let _ = self.paths.send(root);
+                }
+            }
+            Err(err) => self.report(root, err),
+        }
+    }
+
+    /// Sends the files below `dir`, and stops early once nobody takes them anymore
+    fn walk_dir(&mut self, dir: &Path, depth: usize, root_device: Option<u64>) -> Result<(), ()> {
+        if self.options.max_depth.is_some_and(|max| depth > max) {
+            return Ok(());
+        }
+        if self.options.follow_symlinks {
+            let canonical = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+            if !self.visited.insert(canonical) {
+                return Ok(());
+            }
+        }
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.report(dir.to_path_buf(), err);
+                return Ok(());
+            }
+        };
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) => paths.push(entry.path()),
+                Err(err) => self.report(dir.to_path_buf(), err),
+            }
+        }
+        paths.sort();
+
+        for path in paths {
+            let metadata = match self.metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    self.report(path, err);
+                    continue;
+                }
+            };
+            let relative = path.strip_prefix(&self.root).unwrap_or(&path);
+            if self.is_excluded(relative, metadata.is_dir()) {
+                continue;
+            }
+            if metadata.is_dir() {
+                if self.options.same_device
+                    && root_device.is_some()
+                    && device(&metadata) != root_device
+                {
+                    continue;
+                }
+                self.walk_dir(&path, depth + 1, root_device)?;
+            } else if self.accepts_size(&metadata) {
+                self.paths.send(path).map_err(|_| ())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        if self.options.follow_symlinks {
+            fs::metadata(path)
+        } else {
+            fs::symlink_metadata(path)
+        }
+    }
+
+    fn accepts_size(&self, metadata: &Metadata) -> bool {
+        if !metadata.is_file() {
+            return true;
+        }
+        let len = metadata.len();
+        self.options.min_size.is_none_or(|min| len >= min)
+            && self.options.max_size.is_none_or(|max| len <= max)
+    }
+
+    fn is_excluded(&self, relative: &Path, is_dir: bool) -> bool {
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let mut excluded = false;
+        for exclude in &self.options.excludes {
+            if exclude.negated == excluded && exclude.matches(&relative, is_dir) {
+                excluded = !exclude.negated;
+            }
+        }
+        excluded
+    }
+
+    fn report(&self, path: PathBuf, err: io::Error) {
+        let err = FileMagicError::new(
+            ErrorKind::Io(err.kind()),
+            format!("cannot read `{}' ({})", path.display(), err),
+        )
+        .with_errno(err.raw_os_error())
+        .with_path(Some(&path));
+        let _ = self.results.send((path, Err(err)));
+    }
+}
+
+#[cfg(unix)]
+fn device(metadata: &Metadata) -> Option<u64> {
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// A `.gitignore`-style pattern
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Exclude {
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    // matched against the whole relative path rather than the file name
+    anchored: bool,
+}
+
+impl Exclude {
+    /// Parses a `.gitignore` line, which is `None` for blank lines and comments
+    pub(crate) fn parse(line: &str) -> Option<Exclude> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        Some(Exclude {
+            pattern: line.strip_prefix('/').unwrap_or(line).to_string(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Returns whether the path `relative` to the root matches
+    pub(crate) fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let text = if self.anchored {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        glob(self.pattern.as_bytes(), text.as_bytes())
+    }
+}
+
+/// Matches `text` against the glob `pattern`, where only `**` matches across `/`
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // any number of directories, including none
+            glob(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &c)| c == b'/' && glob(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => matches!(text, [c, ..] if *c != b'/') && glob(rest, &text[1..]),
+        [b'[', class @ ..] => match (class_len(class), text) {
+            (Some(len), [c, ..]) => {
+                *c != b'/'
+                    && class_matches(&class[..len], *c)
+                    && glob(&class[len + 1..], &text[1..])
+            }
+            (Some(_), []) => false,
+            // an unclosed `[` is literal
+            (None, _) => text.first() == Some(&b'[') && glob(class, &text[1..]),
+        },
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+/// Returns the length of the character class at the start of `class`, before its `]`
+fn class_len(class: &[u8]) -> Option<usize> {
+    let start = match class {
+        [b'!' | b'^', b']', ..] => 2,
+        [b'!' | b'^', ..] | [b']', ..] => 1,
+        _ => 0,
+    };
+    class[start..]
+        .iter()
+        .position(|&c| c == b']')
+        .map(|i| start + i)
+}
+
+fn class_matches(class: &[u8], c: u8) -> bool {
+    let (negated, mut class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    while let Some(&first) = class.first() {
+        match class {
+            [low, b'-', high, rest @ ..] => {
+                found |= (*low..=*high).contains(&c);
+                class = rest;
+            }
+            [_, rest @ ..] => {
+                found |= first == c;
+                class = rest;
+            }
+            [] => break,
+        }
+    }
+    found != negated
+}
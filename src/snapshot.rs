@@ -0,0 +1,94 @@
+//! In-memory copies of the data libmagic looks at
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::fd::FromRawFd;
+
+use crate::{FileMagicError, Magic};
+
+/// A copy of the windows of some data that libmagic looks at, to detect it once or more
+pub(crate) enum Snapshot {
+    /// A sparse anonymous file of the same size as the data, which lets libmagic test
+    /// the end of the data too
+    #[cfg(unix)]
+    File(File),
+    /// The start of the data
+    #[cfg(not(unix))]
+    Buffer(Vec<u8>),
+}
+
+impl Snapshot {
+    /// Copies the windows of the `len` bytes `reader` holds from its position `start`
+    ///
+    /// `reader` is expected at `start` and left at an unspecified position.
+    #[cfg(unix)]
+    pub(crate) fn take<R: Read + Seek>(
+        reader: &mut R,
+        start: u64,
+        len: u64,
+        bytes_max: u64,
+    ) -> io::Result<Snapshot> {
+        let mut file = anonymous_file()?;
+
+        // a sparse copy of the same size, so that libmagic finds the tail where it expects
+        file.set_len(len)?;
+        io::copy(&mut reader.take(bytes_max.min(len)), &mut file)?;
+        if len > bytes_max {
+            let tail = (len - bytes_max).max(bytes_max);
+            reader.seek(SeekFrom::Start(start + tail))?;
+            file.seek(SeekFrom::Start(tail))?;
+            io::copy(reader, &mut file)?;
+        }
+        Ok(Snapshot::File(file))
+    }
+
+    /// Copies the window at the start of the `len` bytes `reader` holds from its
+    /// position `start`
+    ///
+    /// `reader` is expected at `start` and left at an unspecified position.
+    #[cfg(not(unix))]
+    pub(crate) fn take<R: Read + Seek>(
+        reader: &mut R,
+        _start: u64,
+        len: u64,
+        bytes_max: u64,
+    ) -> io::Result<Snapshot> {
+        let mut buffer = Vec::new();
+        reader.take(bytes_max.min(len)).read_to_end(&mut buffer)?;
+        Ok(Snapshot::Buffer(buffer))
+    }
+
+    /// Returns a textual description of the copied data
+    pub(crate) fn detect(&self, cookie: &Magic<'_>) -> Result<String, FileMagicError> {
+        match self {
+            #[cfg(unix)]
+            Snapshot::File(file) => {
+                // libmagic reads from the current offset
+                (&*file)
+                    .seek(SeekFrom::Start(0))
+                    .map_err(FileMagicError::io)?;
+                cookie.descriptor(file)
+            }
+            #[cfg(not(unix))]
+            Snapshot::Buffer(buffer) => cookie.buffer(buffer),
+        }
+    }
+}
+
+/// Creates a file that lives in memory and goes away with its last descriptor
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn anonymous_file() -> io::Result<File> {
+    let fd = unsafe { libc::memfd_create(c"filemagic".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Creates a temporary file that goes away with its last descriptor
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn anonymous_file() -> io::Result<File> {
+    crate::capture::anonymous_file()
+}
@@ -0,0 +1,42 @@
+//! Whether a `Magic` cookie has a database loaded yet
+
+/// A cookie without a database, as returned by `Magic::open()`
+///
+/// Only the database functions, like `load()`, `check()` and `compile()`, and the
+/// flag and parameter functions are available.
+///
+/// ```compile_fail
+/// use filemagic::{Flags, Magic};
+///
+/// let cookie = Magic::open(Flags::NONE).unwrap();
+/// cookie.file("path/to/file");
+/// ```
+#[derive(Debug)]
+pub enum Unloaded {}
+
+/// A cookie with a database loaded, which can look at data
+///
+/// `check()`, `compile()` and `list()` would replace the loaded database, so they are
+/// only available before loading one.
+///
+/// ```compile_fail
+/// use filemagic::{Flags, Magic};
+///
+/// let cookie = Magic::open(Flags::NONE).unwrap().load_default().unwrap();
+/// cookie.check(&["path/to/db"]);
+/// ```
+#[derive(Debug)]
+pub enum Loaded {}
+
+/// The states of a `Magic` cookie, which are `Unloaded` and `Loaded`
+pub trait State: private::Sealed {}
+
+impl State for Unloaded {}
+impl State for Loaded {}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for super::Unloaded {}
+    impl Sealed for super::Loaded {}
+}
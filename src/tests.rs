@@ -1,6 +1,16 @@
+// the original tests pass paths and database lists in a few equivalent ways
+#![allow(
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::useless_vec
+)]
+
 extern crate regex;
 
-use super::{version as ver, Flags, Magic};
+use super::{
+    version as ver, AppleType, Capabilities, ErrorKind, Flags, Magic, MagicBuilder, Match, Mime,
+    Param, Severity, Source,
+};
 
 #[test]
 fn version() {
@@ -8,22 +18,107 @@ fn version() {
     assert!(re.is_match(ver()));
 }
 
+#[test]
+fn libmagic_version() {
+    let (major, minor) = super::libmagic_version();
+    assert_eq!(major, 5);
+    assert!(minor >= 21);
+}
+
+#[test]
+fn capabilities() {
+    let cookie = Magic::open(Flags::MIME_TYPE)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+
+    let flags = cookie.flags();
+    let capabilities = cookie.capabilities().unwrap();
+    assert_eq!(capabilities.version, super::libmagic_version());
+    assert!(capabilities.flags.contains(Flags::MIME | Flags::ERROR));
+    assert!(capabilities.params.contains(&Param::IndirMax));
+    assert_eq!(cookie.flags(), flags);
+
+    assert_eq!(Capabilities::probe().unwrap(), capabilities);
+}
+
 #[test]
 fn load_default_db() {
-    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
-    assert!(cookie.load::<String>(&[]).is_ok());
+    assert!(Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .is_ok());
 }
 
 #[test]
 fn load_one_db() {
+    assert!(Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&vec!["data/db-images-png"])
+        .is_ok());
+}
+
+#[test]
+fn load_multiple_dbs() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png", "data/db-python"])
+        .unwrap();
+
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    assert_eq!(
+        cookie
+            .buffer(b"#!/usr/bin/env python\nprint('Hello')")
+            .unwrap(),
+        "Python script, ASCII text executable"
+    );
+}
+
+#[test]
+fn load_db_path_with_separator() {
     let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
-    assert!(cookie.load(&vec!["data/db-images-png"]).is_ok());
+    let separated = format!("data/db-images-png{}data/db-python", super::PATH_SEPARATOR);
+    assert!(cookie.load(&[separated]).is_err());
+}
+
+/// Compiles `data/db-images-png` for the linked libmagic, whose compiled format may differ
+/// from that of the precompiled fixture
+fn compiled_png_db() -> Vec<u8> {
+    Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .compile_to_vec(&[Source::file("data/db-images-png")])
+        .unwrap()
+}
+
+#[test]
+fn load_db_from_buffer() {
+    let db = compiled_png_db();
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load_buffers(&[&db])
+        .unwrap();
+
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+}
+
+#[test]
+fn load_invalid_db_from_buffer() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    assert!(cookie.load_buffers(&[b"not a magic database"]).is_err());
 }
 
 #[test]
 fn get_file_mime() {
-    let cookie = Magic::open(Flags::NONE).unwrap();
-    assert!(cookie.load(&vec!["data/db-images-png"]).is_ok());
+    let cookie = Magic::open(Flags::NONE)
+        .unwrap()
+        .load(&vec!["data/db-images-png"])
+        .unwrap();
 
     let path = "data/rust-logo-128x128-blk.png";
 
@@ -32,17 +127,21 @@ fn get_file_mime() {
         "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
     );
 
-    cookie.set_flags(Flags::MIME_TYPE);
+    cookie.set_flags(Flags::MIME_TYPE).unwrap();
     assert_eq!(cookie.file(&path).unwrap(), "image/png");
 
-    cookie.set_flags(Flags::MIME_TYPE | Flags::MIME_ENCODING);
+    cookie
+        .set_flags(Flags::MIME_TYPE | Flags::MIME_ENCODING)
+        .unwrap();
     assert_eq!(cookie.file(&path).unwrap(), "image/png; charset=binary");
 }
 
 #[test]
 fn get_buffer_mime() {
-    let cookie = Magic::open(Flags::NONE).unwrap();
-    assert!(cookie.load(&vec!["data/db-python"].as_slice()).is_ok());
+    let cookie = Magic::open(Flags::NONE)
+        .unwrap()
+        .load(&vec!["data/db-python"].as_slice())
+        .unwrap();
 
     let s = b"#!/usr/bin/env python\nprint('Hello, world!')";
     assert_eq!(
@@ -50,14 +149,16 @@ fn get_buffer_mime() {
         "Python script, ASCII text executable"
     );
 
-    cookie.set_flags(Flags::MIME_TYPE);
+    cookie.set_flags(Flags::MIME_TYPE).unwrap();
     assert_eq!(cookie.buffer(s).unwrap(), "text/x-python");
 }
 
 #[test]
 fn file_error() {
-    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
-    assert!(cookie.load::<String>(&[]).is_ok());
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
 
     let ret = cookie.file("non-existent_file.txt");
     assert!(ret.is_err());
@@ -67,6 +168,297 @@ fn file_error() {
     );
 }
 
+#[cfg(unix)]
+#[test]
+fn get_descriptor_mime() {
+    use std::io::{Seek, SeekFrom};
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+
+    let mut file = std::fs::File::open("data/rust-logo-128x128-blk.png").unwrap();
+    assert_eq!(
+        cookie.descriptor_file(&file).unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    assert_eq!(file.stream_position().unwrap(), 0);
+
+    // detection starts at the current offset, which is left untouched
+    file.seek(SeekFrom::Start(8)).unwrap();
+    assert_eq!(cookie.descriptor(&file).unwrap(), "data");
+    assert_eq!(file.stream_position().unwrap(), 8);
+}
+
+#[test]
+fn get_reader_mime() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+
+    let file = std::fs::File::open("data/rust-logo-128x128-blk.png").unwrap();
+    assert_eq!(
+        cookie.reader(file).unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn get_seekable_reader_tail() {
+    use std::io::{Cursor, Seek};
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-trailer"])
+        .unwrap();
+
+    // larger than both windows libmagic looks at by default
+    let mut data = vec![0u8; 16 * 1024 * 1024];
+    data.extend_from_slice(b"TAIL");
+
+    assert_eq!(cookie.reader(Cursor::new(&data)).unwrap(), "data");
+
+    let mut cursor = Cursor::new(&data);
+    assert_eq!(cookie.seekable_reader(&mut cursor).unwrap(), "trailer data");
+    assert_eq!(cursor.stream_position().unwrap(), 0);
+}
+
+#[test]
+fn pool_get_mime() {
+    use crate::MagicPool;
+
+    let pool = MagicPool::new(Flags::NONE, &["data/db-images-png"], 2);
+    let path = "data/rust-logo-128x128-blk.png";
+    let expected = "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced";
+
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let cookie = pool.get().unwrap();
+                assert_eq!(cookie.file(path).unwrap(), expected);
+            });
+        }
+    });
+
+    // flags changed on a checked out cookie don't leak into the next checkout
+    let first = pool.get().unwrap();
+    let second = pool.get().unwrap();
+    assert!(pool.try_get().is_none());
+    first.set_flags(Flags::MIME_TYPE).unwrap();
+    second.set_flags(Flags::MIME_TYPE).unwrap();
+    assert_eq!(first.file(path).unwrap(), "image/png");
+    drop((first, second));
+    assert_eq!(pool.get().unwrap().file(path).unwrap(), expected);
+}
+
+#[test]
+fn pool_reset() {
+    let pool = crate::MagicPool::new(Flags::NONE, &["data/db-images-png"], 1);
+    let bytes_max = pool.get().unwrap().get_param(Param::BytesMax).unwrap();
+
+    let cookie = pool.get().unwrap();
+    cookie.set_param(Param::BytesMax, 16).unwrap();
+    cookie.set_flags(Flags::MIME_TYPE).unwrap();
+    drop(cookie);
+
+    let cookie = pool.get().unwrap();
+    assert_eq!(cookie.get_param(Param::BytesMax).unwrap(), bytes_max);
+    assert!(!cookie.flags().contains(Flags::MIME_TYPE));
+}
+
+#[test]
+fn pool_load_error() {
+    let pool = crate::MagicPool::new(Flags::NONE, &["data/non-existent-db"], 1);
+    assert!(pool.get().is_err());
+    assert!(pool.get().is_err());
+}
+
+#[test]
+fn identify_file() {
+    let cookie = Magic::open(Flags::MIME_TYPE)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+
+    let path = "data/rust-logo-128x128-blk.png";
+    let file_type = cookie.identify(path).unwrap();
+    assert_eq!(
+        file_type.description,
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    assert_eq!(file_type.mime_type, "image/png");
+    assert_eq!(file_type.mime_encoding, "binary");
+    assert_eq!(file_type.extensions, vec!["png"]);
+    assert_eq!(file_type.apple, None);
+
+    // the flags of the cookie are left alone
+    assert_eq!(cookie.file(path).unwrap(), "image/png");
+
+    let file_type = cookie.identify("data").unwrap();
+    assert_eq!(file_type.description, "directory");
+    assert_eq!(file_type.mime_type, "inode/directory");
+    assert!(file_type.extensions.is_empty());
+
+    assert!(cookie.identify("non-existent_file.txt").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn identify_setuid_file() {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+
+    let path = std::env::temp_dir().join(format!("filemagic-setuid-{}", std::process::id()));
+    fs::copy("data/rust-logo-128x128-blk.png", &path).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o4644)).unwrap();
+    let description = cookie.file(&path);
+    let file_type = cookie.identify(&path);
+    fs::remove_file(&path).unwrap();
+
+    // libmagic describes the mode too, which the description has to match
+    let description = description.unwrap();
+    assert_eq!(
+        description,
+        "setuid PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    let file_type = file_type.unwrap();
+    assert_eq!(file_type.description, description);
+    assert_eq!(file_type.mime_type, "image/png");
+}
+
+#[test]
+fn identify_buffer() {
+    let cookie = Magic::open(Flags::NONE)
+        .unwrap()
+        .load(&["data/db-python"])
+        .unwrap();
+
+    let file_type = cookie
+        .identify_buffer(b"#!/usr/bin/env python\nprint('Hello, world!')")
+        .unwrap();
+    assert_eq!(
+        file_type.description,
+        "Python script, ASCII text executable"
+    );
+    assert_eq!(file_type.mime_type, "text/x-python");
+    assert_eq!(file_type.mime_encoding, "us-ascii");
+}
+
+#[test]
+fn set_and_get_params() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+
+    // unsupported parameters are refused rather than ignored
+    let linked = super::version::libmagic_version_number();
+    for &param in Param::ALL {
+        assert_eq!(
+            cookie.get_param(param).is_ok(),
+            linked >= param.min_version()
+        );
+    }
+
+    assert!(cookie.set_param(Param::IndirMax, 10).is_ok());
+    assert_eq!(cookie.get_param(Param::IndirMax).unwrap(), 10);
+
+    // too few bytes to see the whole PNG signature
+    assert!(cookie.set_param(Param::BytesMax, 4).is_ok());
+    assert_eq!(cookie.get_param(Param::BytesMax).unwrap(), 4);
+    let file = std::fs::File::open("data/rust-logo-128x128-blk.png").unwrap();
+    assert_eq!(
+        cookie.reader(file).unwrap(),
+        "Non-ISO extended-ASCII text, with no line terminators"
+    );
+}
+
+#[test]
+fn set_and_get_flags() {
+    let cookie = Magic::open(Flags::MIME_TYPE).unwrap();
+    assert_eq!(cookie.flags(), Flags::MIME_TYPE | Flags::ERROR);
+
+    assert!(cookie.set_flags(Flags::EXTENSION | Flags::SYMLINK).is_ok());
+    assert_eq!(cookie.flags(), Flags::EXTENSION | Flags::SYMLINK);
+
+    // unknown bits are refused, and named
+    let ret = cookie.set_flags(Flags::MIME_TYPE | Flags::from_bits_retain(0x4000_0000));
+    assert_eq!(
+        ret.err().unwrap().desc,
+        format!(
+            "flags `0x40000000' are not supported by libmagic {}.{}",
+            super::version::libmagic_version_number() / 100,
+            super::version::libmagic_version_number() % 100
+        )
+    );
+    assert_eq!(cookie.flags(), Flags::EXTENSION | Flags::SYMLINK);
+}
+
+#[test]
+fn unsupported_flags() {
+    assert_eq!(
+        (Flags::EXTENSION | Flags::NO_CHECK_CSV | Flags::MIME_TYPE).unsupported_by(522),
+        Flags::EXTENSION | Flags::NO_CHECK_CSV
+    );
+    assert!((Flags::EXTENSION | Flags::NO_CHECK_CSV)
+        .unsupported_by(545)
+        .is_empty());
+}
+
+#[test]
+fn file_error_kind() {
+    use std::{io, path::Path};
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+
+    let err = cookie.file("non-existent_file.txt").unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Io(io::ErrorKind::NotFound));
+    assert_eq!(err.errno, Some(libc::ENOENT));
+    assert_eq!(
+        err.path.as_deref(),
+        Some(Path::new("non-existent_file.txt"))
+    );
+
+    let err = io::Error::from(err);
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(
+        err.to_string(),
+        "cannot stat `non-existent_file.txt' (No such file or directory)"
+    );
+}
+
+#[test]
+fn load_error_kind() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+
+    let err = cookie.load(&["data/non-existent-db"]).err().unwrap();
+    assert_eq!(err.kind, ErrorKind::DatabaseLoad);
+    assert_eq!(
+        err.path.as_deref(),
+        Some(std::path::Path::new("data/non-existent-db"))
+    );
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    let err = cookie
+        .set_flags(Flags::from_bits_retain(0x4000_0000))
+        .unwrap_err();
+    assert_eq!(err.kind, ErrorKind::InvalidFlags);
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+}
+
 #[test]
 fn macro_load_default_db() {
     assert!(magic!().is_ok());
@@ -92,17 +484,21 @@ fn macro_get_file_mime() {
         "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
     );
 
-    cookie.set_flags(Flags::MIME_TYPE);
+    cookie.set_flags(Flags::MIME_TYPE).unwrap();
     assert_eq!(cookie.file(&path).unwrap(), "image/png");
 
-    cookie.set_flags(Flags::MIME_TYPE | Flags::MIME_ENCODING);
+    cookie
+        .set_flags(Flags::MIME_TYPE | Flags::MIME_ENCODING)
+        .unwrap();
     assert_eq!(cookie.file(&path).unwrap(), "image/png; charset=binary");
 }
 
 #[test]
 fn macro_get_buffer_mime() {
-    let cookie = magic!().unwrap();
-    assert!(cookie.load(&vec!["data/db-python"].as_slice()).is_ok());
+    let cookie = magic!()
+        .unwrap()
+        .load(&vec!["data/db-python"].as_slice())
+        .unwrap();
 
     let s = b"#!/usr/bin/env python\nprint('Hello, world!')";
     assert_eq!(
@@ -110,6 +506,701 @@ fn macro_get_buffer_mime() {
         "Python script, ASCII text executable"
     );
 
-    cookie.set_flags(Flags::MIME_TYPE);
+    cookie.set_flags(Flags::MIME_TYPE).unwrap();
     assert_eq!(cookie.buffer(s).unwrap(), "text/x-python");
 }
+
+#[cfg(feature = "embedded-db")]
+#[test]
+fn load_embedded_db() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load_default_embedded()
+        .unwrap();
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_get_mime() {
+    use crate::AsyncMagic;
+
+    let magic = AsyncMagic::new(Flags::NONE | Flags::ERROR, &["data/db-images-png"], 2).unwrap();
+    let path = "data/rust-logo-128x128-blk.png";
+    let expected = "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced";
+
+    assert_eq!(magic.file(path).await.unwrap(), expected);
+    assert_eq!(
+        magic.buffer(std::fs::read(path).unwrap()).await.unwrap(),
+        expected
+    );
+    let file = tokio::fs::File::open(path).await.unwrap();
+    assert_eq!(magic.reader(file).await.unwrap(), expected);
+
+    // more detections than cookies, all at once
+    let detections = (0..8).map(|_| {
+        let magic = magic.clone();
+        tokio::spawn(async move { magic.file(path).await })
+    });
+    for detection in detections.collect::<Vec<_>>() {
+        assert_eq!(detection.await.unwrap().unwrap(), expected);
+    }
+}
+
+#[test]
+fn file_path_with_nul() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+
+    let err = cookie.file("data/rust-logo\0.png").unwrap_err();
+    assert_eq!(err.kind, ErrorKind::InvalidPath);
+    assert!(err.path.is_some());
+}
+
+#[cfg(unix)]
+#[test]
+fn file_non_utf8_path() {
+    use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+
+    let mut name = b"filemagic-logo-\xe9-".to_vec();
+    name.extend_from_slice(std::process::id().to_string().as_bytes());
+    let path = std::env::temp_dir().join(OsStr::from_bytes(&name));
+    fs::copy("data/rust-logo-128x128-blk.png", &path).unwrap();
+    let description = cookie.file(&path);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        description.unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+}
+
+#[test]
+fn buffer_non_utf8_output() {
+    let cookie = Magic::open(Flags::RAW | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-name"])
+        .unwrap();
+
+    let data = b"NAME:caf\xe9\n";
+    assert_eq!(
+        cookie.buffer_bytes(data).unwrap(),
+        b"name file, named caf\xe9".to_vec()
+    );
+    assert_eq!(cookie.buffer(data).unwrap(), "name file, named caf\u{fffd}");
+}
+
+#[test]
+fn buffer_all_matches() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-polyglot"])
+        .unwrap();
+
+    let descriptions = |data: &[u8]| {
+        cookie
+            .buffer_all(data)
+            .unwrap()
+            .into_iter()
+            .map(|m: Match| m.description)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        descriptions(b"POLYGLOTab\ncd\n"),
+        ["second format, named ab", "first format", "ASCII text"]
+    );
+    // an escaped separator in the data is not a separator
+    assert_eq!(
+        descriptions(b"POLYGLOTab\\012- \xe9\x01"),
+        ["second format, named ab\\012- \\351\\001", "first format"]
+    );
+    assert_eq!(descriptions(b"\x00\x01"), ["data"]);
+    assert!(!cookie.flags().contains(Flags::CONTINUE));
+}
+
+#[test]
+fn file_all_matches() {
+    let cookie = Magic::open(Flags::RAW | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+
+    let matches = cookie.file_all("data/rust-logo-128x128-blk.png").unwrap();
+    assert_eq!(
+        matches,
+        [Match {
+            description: "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced".to_string()
+        }]
+    );
+}
+
+#[test]
+fn extensions() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+
+    assert_eq!(
+        cookie.extensions("data/rust-logo-128x128-blk.png").unwrap(),
+        Some(vec!["png".to_string()])
+    );
+    assert_eq!(
+        cookie
+            .buffer_extensions(b"\xff\xd8\xff\xe0\x00\x10JFIF\x00")
+            .unwrap(),
+        Some(vec![
+            "jpeg".to_string(),
+            "jpg".to_string(),
+            "jpe".to_string(),
+            "jfif".to_string()
+        ])
+    );
+    assert_eq!(cookie.buffer_extensions(b"").unwrap(), None);
+    assert!(!cookie.flags().contains(Flags::EXTENSION));
+}
+
+#[test]
+fn mime() {
+    let cookie = Magic::open(Flags::CONTINUE | Flags::ERROR)
+        .unwrap()
+        .load::<String>(&[])
+        .unwrap();
+    let flags = cookie.flags();
+
+    let mime = cookie.mime("data/rust-logo-128x128-blk.png").unwrap();
+    assert_eq!(mime.essence(), "image/png");
+    assert_eq!(mime.charset(), Some("binary"));
+    assert_eq!(mime.to_string(), "image/png; charset=binary");
+
+    let mime = cookie.mime_buffer(b"hello world\n").unwrap();
+    assert_eq!(
+        mime,
+        Mime {
+            top_level: "text".to_string(),
+            subtype: "plain".to_string(),
+            suffix: None,
+            params: vec![("charset".to_string(), "us-ascii".to_string())],
+        }
+    );
+    assert_eq!(cookie.flags(), flags);
+}
+
+#[test]
+fn parse_mime() {
+    let mime = super::mime::parse_mime("Image/SVG+XML; Charset=\"utf-8\"; x=a\\\"b").unwrap();
+    assert_eq!(mime.top_level, "image");
+    assert_eq!(mime.subtype, "svg");
+    assert_eq!(mime.suffix.as_deref(), Some("xml"));
+    assert_eq!(mime.charset(), Some("utf-8"));
+    assert_eq!(mime.param("X"), Some("a\\\"b"));
+    assert_eq!(
+        mime.to_string(),
+        "image/svg+xml; charset=utf-8; x=\"a\\\\\\\"b\""
+    );
+    assert_eq!(super::mime::parse_mime("data"), None);
+}
+
+#[cfg(feature = "mime")]
+#[test]
+fn mime_into_mime_crate() {
+    let mime = super::mime::parse_mime("image/svg+xml; charset=utf-8").unwrap();
+    let converted = ::mime::Mime::try_from(&mime).unwrap();
+    assert_eq!(converted.essence_str(), ::mime::IMAGE_SVG.essence_str());
+    assert_eq!(converted.get_param(::mime::CHARSET).unwrap(), "utf-8");
+}
+
+#[test]
+fn apple_type() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-apple"])
+        .unwrap();
+    let flags = cookie.flags();
+
+    let apple = cookie.buffer_apple_type(b"APPL").unwrap().unwrap();
+    assert_eq!(
+        apple,
+        AppleType {
+            creator: *b"ABCD",
+            type_code: *b"TEXT",
+        }
+    );
+    assert_eq!(apple.creator_str(), "ABCD");
+    assert_eq!(apple.type_str(), "TEXT");
+    assert_eq!(apple.to_string(), "ABCD/TEXT");
+    assert_eq!(&apple.to_bytes(), b"ABCDTEXT");
+    assert_eq!(cookie.buffer_apple_type(b"other").unwrap(), None);
+    assert_eq!(cookie.identify_buffer(b"APPL").unwrap().apple, Some(apple));
+    assert_eq!(cookie.flags(), flags);
+}
+
+#[test]
+fn builder() {
+    let cookie = MagicBuilder::new()
+        .flags(Flags::MIME_TYPE)
+        .database("data/db-images-png")
+        .database("data/db-python")
+        .param(Param::BytesMax, 4096)
+        .build()
+        .unwrap();
+    assert!(cookie.flags().contains(Flags::MIME_TYPE | Flags::ERROR));
+    assert_eq!(cookie.get_param(Param::BytesMax).unwrap(), 4096);
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "image/png"
+    );
+
+    let db = compiled_png_db();
+    let cookie = Magic::builder()
+        .flags(Flags::MIME_TYPE)
+        .database_bytes(&db)
+        .build()
+        .unwrap();
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "image/png"
+    );
+}
+
+#[test]
+fn builder_errors() {
+    let err = MagicBuilder::new()
+        .database("data/db-images-png")
+        .database("data/db-missing")
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrorKind::DatabaseLoad);
+    assert_eq!(
+        err.path.as_deref(),
+        Some(std::path::Path::new("data/db-missing"))
+    );
+
+    let db = compiled_png_db();
+    let err = MagicBuilder::new()
+        .database("data/db-images-png")
+        .database_bytes(&db)
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrorKind::DatabaseLoad);
+}
+
+#[test]
+fn compile_to_vec() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    let db = cookie
+        .compile_to_vec(&[
+            Source::file("data/db-images-png"),
+            Source::rules("0\tstring\tNAME:\tname file\n"),
+        ])
+        .unwrap();
+
+    let cookie = cookie.load_buffers(&[&db]).unwrap();
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    assert_eq!(cookie.buffer(b"NAME:").unwrap(), "name file");
+
+    // the loaded database stays, and nothing is written into the current directory
+    cookie
+        .compile_to_vec(&[Source::file("data/db-python")])
+        .unwrap();
+    assert_eq!(cookie.buffer(b"NAME:").unwrap(), "name file");
+    #[cfg(unix)]
+    assert!(!std::fs::read_dir(".").unwrap().any(|entry| {
+        let name = entry.unwrap().file_name();
+        let name = name.to_string_lossy();
+        name.starts_with("filemagic-") && name.ends_with(".mgc")
+    }));
+
+    let rules = std::fs::read_to_string("data/db-invalid").unwrap();
+    let err = cookie
+        .compile_to_vec(&[Source::rules(&rules)])
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrorKind::DatabaseLoad);
+}
+
+#[test]
+fn compile_directory_to_vec() {
+    use std::fs;
+
+    // laid out like `file/magic/Magdir`, with a subdirectory libmagic skips
+    let dir = std::env::temp_dir().join(format!("filemagic-magdir-{}", std::process::id()));
+    fs::create_dir_all(dir.join("skipped")).unwrap();
+    fs::copy("data/db-images-png", dir.join("images")).unwrap();
+    fs::copy("data/db-python", dir.join("python")).unwrap();
+    fs::copy("data/db-invalid", dir.join("skipped").join("invalid")).unwrap();
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    let db = cookie.compile_to_vec(&[Source::file(&dir)]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let db = db.unwrap();
+    let cookie = cookie.load_buffers(&[&db]).unwrap();
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    assert_eq!(
+        cookie
+            .buffer(b"#!/usr/bin/env python\nprint('Hello')")
+            .unwrap(),
+        "Python script, ASCII text executable"
+    );
+}
+
+#[test]
+fn compile_to() {
+    let output = std::env::temp_dir().join(format!("filemagic-test-{}.mgc", std::process::id()));
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    assert!(cookie
+        .compile_to(&[Source::file("data/db-python")], &output)
+        .is_ok());
+
+    let cookie = cookie.load(&[&output]);
+    std::fs::remove_file(&output).unwrap();
+    assert_eq!(
+        cookie
+            .unwrap()
+            .buffer(b"#!/usr/bin/env python\nprint('Hello')")
+            .unwrap(),
+        "Python script, ASCII text executable"
+    );
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    let err = cookie
+        .compile_to(&[Source::file("data/non-existent-db")], &output)
+        .unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Io(std::io::ErrorKind::NotFound));
+    assert!(!output.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn list_entries() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    let entries = cookie
+        .list_entries(&["data/db-images-png", "data/db-polyglot"])
+        .unwrap();
+
+    let png = &entries[0];
+    assert!(png.strength > 0);
+    assert_eq!(png.line, 1);
+    assert!(!png.text);
+    assert_eq!(png.offset.as_deref(), Some("0"));
+    assert_eq!(png.test_type.as_deref(), Some("string"));
+    assert_eq!(
+        png.test_value.as_deref(),
+        Some("\\x89PNG\\x0d\\x0a\\x1a\\x0a")
+    );
+    assert_eq!(png.description, "PNG image data");
+    assert_eq!(png.mime_type.as_deref(), Some("image/png"));
+
+    let descriptions: Vec<_> = entries[1..]
+        .iter()
+        .map(|entry| entry.description.as_str())
+        .collect();
+    assert_eq!(descriptions, ["second format", "first format"]);
+    assert_eq!(entries[1].mime_type, None);
+}
+
+#[cfg(unix)]
+#[test]
+fn list_entries_extensions() {
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+    let entries = cookie.list_entries(&["data/db-apple"]).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].test_value.as_deref(), Some("APPL"));
+    assert_eq!(entries[0].extensions, ["appl", "apl"]);
+
+    // the listed database isn't loaded into the cookie
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    assert!(cookie
+        .list_entries(&["data/non-existent-db"])
+        .is_err_and(|err| err.kind == ErrorKind::DatabaseLoad));
+}
+
+#[cfg(unix)]
+#[test]
+fn check_report() {
+    use std::path::Path;
+
+    let cookie = Magic::open(Flags::NONE | Flags::ERROR).unwrap();
+    let report = cookie.check_report(&["data/db-invalid"]).unwrap();
+    assert!(!report.is_ok());
+
+    // libmagic also warns about using the source rather than a compiled database
+    let warnings: Vec<_> = report
+        .warnings()
+        .filter(|warning| warning.line.is_some())
+        .collect();
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(
+        warnings[0].file.as_deref(),
+        Some(Path::new("data/db-invalid"))
+    );
+    assert_eq!(warnings[0].line, Some(2));
+    assert_eq!(warnings[0].message, "type `bogus\tX\tbad type' invalid");
+    assert_eq!(warnings[1].line, Some(3));
+    assert_eq!(warnings[1].message, "string modifier `Q' invalid");
+
+    let errors: Vec<_> = report.errors().collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].severity, Severity::Error);
+    assert_eq!(
+        errors[0].file.as_deref(),
+        Some(Path::new("data/db-invalid"))
+    );
+    assert!(!cookie.flags().contains(Flags::CHECK));
+
+    let report = cookie.check_report(&["data/db-images-png"]).unwrap();
+    assert!(report.is_ok());
+    assert!(report.warnings().all(|warning| warning.line.is_none()));
+
+    // the checked database isn't loaded into the cookie
+    let cookie = cookie.load(&["data/db-images-png"]).unwrap();
+    assert!(cookie.check_report(&["data/db-python"]).unwrap().is_ok());
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn debug_handler() {
+    use std::sync::{Arc, Mutex};
+
+    let cookie = Magic::open(Flags::ERROR)
+        .unwrap()
+        .load(&["data/db-images-png"])
+        .unwrap();
+    cookie.set_flags(Flags::DEBUG | Flags::ERROR).unwrap();
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let sink = lines.clone();
+    cookie.set_debug_handler(Some(Box::new(move |line: &str| {
+        sink.lock().unwrap().push(line.to_string())
+    })));
+
+    assert_eq!(
+        cookie.file("data/rust-logo-128x128-blk.png").unwrap(),
+        "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced"
+    );
+    let traced = lines.lock().unwrap().clone();
+    assert!(traced.iter().any(|line| line.contains("mget")));
+    assert!(traced.iter().any(|line| line.contains("PNG image data")));
+
+    lines.lock().unwrap().clear();
+    cookie.set_flags(Flags::ERROR).unwrap();
+    cookie.buffer(b"\x89PNG").unwrap();
+    assert!(lines.lock().unwrap().is_empty());
+}
+
+#[test]
+fn scan() {
+    use crate::Scanner;
+    use std::{collections::BTreeMap, fs, path::PathBuf};
+
+    let root = std::env::temp_dir().join(format!("filemagic-scan-{}", std::process::id()));
+    let png = fs::read("data/rust-logo-128x128-blk.png").unwrap();
+    fs::create_dir_all(root.join("a/b/c")).unwrap();
+    fs::create_dir_all(root.join("target/debug")).unwrap();
+    fs::write(root.join("logo.png"), &png).unwrap();
+    fs::write(root.join("a/logo.png"), &png).unwrap();
+    fs::write(root.join("a/b/c/logo.png"), &png).unwrap();
+    fs::write(root.join("target/debug/logo.png"), &png).unwrap();
+    fs::write(root.join("a/small.png"), &png[..8]).unwrap();
+    fs::write(root.join("a/keep.log"), &png).unwrap();
+    fs::write(root.join("a/drop.log"), &png).unwrap();
+
+    let scan = |scanner: Scanner| -> BTreeMap<PathBuf, String> {
+        scanner
+            .database("data/db-images-png")
+            .flags(Flags::MIME_TYPE)
+            .workers(3)
+            .scan(&root)
+            .unwrap()
+            .map(|(path, file_type)| {
+                let relative = path.strip_prefix(&root).unwrap().to_path_buf();
+                (relative, file_type.unwrap().mime_type)
+            })
+            .collect()
+    };
+
+    let found = scan(Scanner::new());
+    assert_eq!(found.len(), 7);
+    assert_eq!(found[&PathBuf::from("a/b/c/logo.png")], "image/png");
+
+    let found = scan(
+        Scanner::new()
+            .exclude("target/")
+            .exclude("*.log")
+            .exclude("!keep.log")
+            .min_size(9)
+            .max_depth(2),
+    );
+    let expected: Vec<PathBuf> = ["a/keep.log", "a/logo.png", "logo.png"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(found.into_keys().collect::<Vec<_>>(), expected);
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(root.join("a"), root.join("a/b/loop")).unwrap();
+        let found = scan(Scanner::new().exclude("/target"));
+        assert_eq!(found[&PathBuf::from("a/b/loop")], "inode/symlink");
+        let found = scan(Scanner::new().exclude("/target").follow_symlinks(true));
+        assert_eq!(found.len(), 6);
+    }
+
+    let err = Scanner::new()
+        .database("data/non-existent-db")
+        .scan(&root)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrorKind::DatabaseLoad);
+    let (path, file_type) = Scanner::new()
+        .scan(root.join("missing"))
+        .unwrap()
+        .next()
+        .unwrap();
+    assert_eq!(path, root.join("missing"));
+    assert_eq!(
+        file_type.err().unwrap().kind,
+        ErrorKind::Io(std::io::ErrorKind::NotFound)
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn scan_excludes() {
+    use crate::scan::Exclude;
+
+    let matches = |pattern: &str, path: &str, is_dir: bool| {
+        Exclude::parse(pattern).unwrap().matches(path, is_dir)
+    };
+    assert!(Exclude::parse("# comment").is_none());
+    assert!(Exclude::parse("  ").is_none());
+    assert!(matches("*.o", "src/main.o", false));
+    assert!(!matches("*.o", "src/main.rs", false));
+    assert!(matches("build/", "a/build", true));
+    assert!(!matches("build/", "a/build", false));
+    assert!(matches("/build", "build", false));
+    assert!(!matches("/build", "a/build", false));
+    assert!(!matches("a/*.c", "a/b/x.c", false));
+    assert!(matches("a/**/x.c", "a/x.c", false));
+    assert!(matches("a/**/x.c", "a/b/c/x.c", false));
+    assert!(matches("**/logs", "deep/down/logs", true));
+    assert!(matches("data/**", "data/a/b", false));
+    assert!(matches("file?.[ch]", "file1.h", false));
+    assert!(!matches("file?.[!ch]", "file1.h", false));
+    assert!(matches("[0-9][0-9]", "42", false));
+    assert!(matches("\\#notes", "#notes", false));
+    assert!(Exclude::parse("!keep").unwrap().matches("keep", false));
+}
+
+#[test]
+fn cached_magic() {
+    use crate::{CachedMagic, Eviction};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("filemagic-cache-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let png = fs::read("data/rust-logo-128x128-blk.png").unwrap();
+    let expected = "PNG image data, 128 x 128, 8-bit/color RGBA, non-interlaced";
+    let load = |db: &str| Magic::open(Flags::NONE).unwrap().load(&[db]).unwrap();
+
+    // same size and modification time, so only a remembered result is still a PNG
+    let scramble = |path: &std::path::Path| {
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        fs::write(path, vec![0; png.len()]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    };
+
+    let mut cookie = CachedMagic::new(load("data/db-images-png"), 4, Eviction::default());
+    let path = dir.join("logo");
+    fs::write(&path, &png).unwrap();
+    assert_eq!(cookie.file(&path).unwrap(), expected);
+    assert_eq!(cookie.identify(&path).unwrap().description, expected);
+    assert_eq!(cookie.len(), 2);
+    scramble(&path);
+    assert_eq!(cookie.file(&path).unwrap(), expected);
+
+    cookie.set_flags(Flags::MIME_TYPE).unwrap();
+    assert_eq!(cookie.file(&path).unwrap(), "application/octet-stream");
+    fs::write(&path, b"changed").unwrap();
+    cookie.set_flags(Flags::ERROR).unwrap();
+    assert_eq!(
+        cookie.file(&path).unwrap(),
+        "ASCII text, with no line terminators"
+    );
+    assert!(cookie.file(dir.join("missing")).is_err());
+
+    assert_eq!(cookie.buffer(&png).unwrap(), expected);
+    fs::write(dir.join("limited"), &png).unwrap();
+    assert_eq!(cookie.file(dir.join("limited")).unwrap(), expected);
+    let bytes_max = cookie.get_param(Param::BytesMax).unwrap();
+    cookie.set_param(Param::BytesMax, 4).unwrap();
+    assert_ne!(cookie.file(dir.join("limited")).unwrap(), expected);
+    cookie.set_param(Param::BytesMax, bytes_max).unwrap();
+    cookie.replace(load("data/db-python"));
+    assert_eq!(cookie.buffer(&png).unwrap(), "data");
+    #[cfg(unix)]
+    {
+        // listing uses a cookie of its own, so the database of this one stays the same
+        cookie.list_entries(&["data/db-images-png"]).unwrap();
+        assert_eq!(cookie.buffer(&png).unwrap(), "data");
+    }
+    cookie.clear();
+    assert!(cookie.is_empty());
+
+    for (eviction, kept, evicted) in [
+        (Eviction::LeastRecentlyUsed, "a", "b"),
+        (Eviction::FirstIn, "b", "a"),
+    ] {
+        let cookie = CachedMagic::new(load("data/db-images-png"), 2, eviction);
+        for name in ["a", "b"] {
+            fs::write(dir.join(name), &png).unwrap();
+            cookie.file(dir.join(name)).unwrap();
+        }
+        cookie.file(dir.join("a")).unwrap();
+        fs::write(dir.join("c"), &png).unwrap();
+        cookie.file(dir.join("c")).unwrap();
+        assert_eq!(cookie.len(), cookie.capacity());
+        scramble(&dir.join(kept));
+        scramble(&dir.join(evicted));
+        assert_eq!(cookie.file(dir.join(kept)).unwrap(), expected);
+        assert_eq!(cookie.file(dir.join(evicted)).unwrap(), "data");
+    }
+
+    let cookie = CachedMagic::new(load("data/db-images-png"), 0, Eviction::FirstIn);
+    assert_eq!(cookie.buffer(&png).unwrap(), expected);
+    assert!(cookie.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,3 +1,7 @@
+use libc::c_int;
+
+use crate::api;
+
 /// Returns the version of this crate in the format `MAJOR.MINOR.PATCH`.
 pub fn version() -> &'static str {
     // TODO: There's also an optional _PRE part
@@ -9,3 +13,14 @@ pub fn version() -> &'static str {
         env!("CARGO_PKG_VERSION_PATCH"),
     )
 }
+
+/// Returns the version of the linked `libmagic` as `(MAJOR, MINOR)`
+pub fn libmagic_version() -> (u32, u32) {
+    let version = libmagic_version_number() as u32;
+    (version / 100, version % 100)
+}
+
+/// Returns the version of the linked `libmagic` as a number such as `545` for 5.45
+pub(crate) fn libmagic_version_number() -> c_int {
+    unsafe { api::magic_version() }
+}